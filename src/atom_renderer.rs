@@ -4,9 +4,10 @@ use glam::Mat4;
 use wgpu::IndexFormat;
 
 use crate::{
+    camera::Camera,
     glue,
     gpubuf::GpuBuf,
-    render_pipeline::{self, AtomCpu, UniformCpu, VertexCpu},
+    render_pipeline::{self, AtomCpu, CameraCpu, UniformCpu, VertexCpu},
 };
 
 pub struct AtomRenderer {
@@ -17,7 +18,9 @@ pub struct AtomRenderer {
     instance_buf: GpuBuf<AtomCpu>,
     render_pipeline: wgpu::RenderPipeline,
     bind_group: wgpu::BindGroup,
-    uniform_buf: GpuBuf<UniformCpu>,
+    uniform_buf: GpuBuf<CameraCpu>,
+    tile_bind_group: wgpu::BindGroup,
+    tile_buf: GpuBuf<UniformCpu>,
 }
 
 impl AtomRenderer {
@@ -52,24 +55,17 @@ impl AtomRenderer {
             Arc::clone(&device),
             Arc::clone(&queue),
             &[],
-            wgpu::BufferUsages::VERTEX,
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         );
 
-        let transform = Mat4::from_cols_array_2d(&[
-            [1.0, 0.0, 0.0, 0.0],
-            [0.0, 1.0, 0.0, 0.0],
-            [0.0, 0.0, 1.0, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
-
         let uniform_buf = GpuBuf::initialize(
             Arc::clone(&device),
             Arc::clone(&queue),
-            &[transform],
+            &[Camera::default().uniform()],
             wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         );
 
-        assert_eq!(bind_group_layouts.len(), 1);
+        assert_eq!(bind_group_layouts.len(), 2);
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layouts[0],
             entries: &[wgpu::BindGroupEntry {
@@ -79,6 +75,21 @@ impl AtomRenderer {
             label: None,
         });
 
+        let tile_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Mat4::IDENTITY],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[1],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(tile_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
         Self {
             vertex_buf,
             index_buf,
@@ -86,6 +97,8 @@ impl AtomRenderer {
             render_pipeline,
             bind_group,
             uniform_buf,
+            tile_bind_group,
+            tile_buf,
             queue,
             device,
         }
@@ -96,12 +109,27 @@ impl AtomRenderer {
             Arc::clone(&self.device),
             Arc::clone(&self.queue),
             &atoms,
-            wgpu::BufferUsages::VERTEX,
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         );
     }
 
-    pub fn set_transform(&mut self, transform: Mat4) {
-        self.uniform_buf.copy_from_slice(&[transform]);
+    /// Like set_atoms but writes into the existing instance buffer when it is large
+    /// enough. Meant for stepping through trajectory frames.
+    pub fn update_atoms(&mut self, atoms: &[AtomCpu]) {
+        if atoms.len() <= self.instance_buf.capacity() {
+            self.instance_buf.copy_from_slice(atoms);
+        } else {
+            self.set_atoms(atoms);
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.uniform_buf.copy_from_slice(&[camera.uniform()]);
+    }
+
+    /// Applied in clip space after everything else, to draw one tile of a larger image.
+    pub fn set_tile(&mut self, tile: Mat4) {
+        self.tile_buf.copy_from_slice(&[tile]);
     }
 
     /// write render commands to the command buffer
@@ -119,6 +147,7 @@ impl AtomRenderer {
         pass.set_vertex_buffer(0, self.vertex_buf.slice().unwrap());
         pass.set_vertex_buffer(1, instance_slice);
         pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, &self.tile_bind_group, &[]);
 
         pass.draw_indexed(
             0..(self.index_buf.len() as u32),
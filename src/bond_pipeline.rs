@@ -0,0 +1,151 @@
+use shame::prelude::*;
+
+use crate::render_pipeline::{light_direction, lighting, AtomCpu, CameraGpu};
+
+/// x runs along the bond from 0 to 1, y across it from -1 to 1. z picks which half of
+/// the bond the quad draws, 0 for the start and 1 for the end.
+pub type CornerCpu = [f32; 3];
+
+type CornerGpu = float3;
+
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct BondCpu {
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub radius: f32,
+    pub start_color: [f32; 3],
+    pub end_color: [f32; 3],
+}
+
+#[derive(shame::Fields)]
+struct BondGpu {
+    start: float3,
+    end: float3,
+    radius: float,
+    start_color: float3,
+    end_color: float3,
+}
+
+type UniformGpu = float4x4;
+
+/// Bonds are this much thinner than the smaller of the two atoms they join, which keeps
+/// their open ends inside the atoms.
+const RADIUS_SCALE: f32 = 0.2;
+
+pub fn features_used() -> wgpu::Features {
+    wgpu::Features::DEPTH_CLIP_CONTROL
+}
+
+/// Bonds between pairs of `atoms`, each half colored like the atom it leads to. Pairs
+/// naming atoms that are not there are left out.
+pub fn bonds(atoms: &[AtomCpu], pairs: &[[u32; 2]]) -> Vec<BondCpu> {
+    pairs
+        .iter()
+        .filter_map(|&[a, b]| {
+            let (a, b) = (atoms.get(a as usize)?, atoms.get(b as usize)?);
+            Some(BondCpu {
+                start: a.pos,
+                end: b.pos,
+                radius: a.radius.min(b.radius) * RADIUS_SCALE,
+                start_color: a.color,
+                end_color: b.color,
+            })
+        })
+        .collect()
+}
+
+/// Cylinders ray cast the way the atoms are, so that bonds and atoms cut into each other
+/// where they meet. Each bond is drawn as two quads covering the whole cylinder, one per
+/// half, and each keeps only the fragments that hit its own half, so the colors meet
+/// exactly at the middle of the bond however it is turned.
+pub fn pipeline(mut f: RenderFeatures) {
+    let index: TriangleList<u32> = f.io.index_buffer();
+
+    let corner: CornerGpu = f.io.vertex_buffer();
+    let bond: BondGpu = f.io.instance_buffer();
+    let camera: CameraGpu = f.io.group().uniform_block();
+    // applied after the quad is sized, like for the atoms
+    let tile: UniformGpu = f.io.group().uniform_block();
+
+    let start = (camera.view * (bond.start, 1.0)).xyz();
+    let end = (camera.view * (bond.end, 1.0)).xyz();
+    let length = (end - start).dot(end - start).sqrt();
+    let axis = (end - start).normalize();
+
+    // where along the axis the camera is, and the way from the axis to the camera
+    let from_start = start * -1.0;
+    let camera_along = from_start.dot(axis);
+    let to_camera = from_start - axis * camera_along;
+    let camera_distance = to_camera.dot(to_camera).sqrt();
+    let facing = to_camera.normalize();
+    let across = (
+        axis.y() * facing.z() - axis.z() * facing.y(),
+        axis.z() * facing.x() - axis.x() * facing.z(),
+        axis.x() * facing.y() - axis.y() * facing.x(),
+    )
+        .rec();
+
+    // The quad lies in the plane touching the front of the cylinder. Every ray that hits
+    // the cylinder crosses that plane within a radius of the axis, and no further past
+    // the ends than this, which grows as the bond turns toward the camera.
+    let overhang = (0.0 - camera_along).max(camera_along - length).max(0.0);
+    let reach = bond.radius + 2.0 * bond.radius * overhang / (camera_distance + bond.radius);
+    let quad_position = start
+        + axis * (length * corner.x() + (corner.x() * 2.0 - 1.0) * reach)
+        + across * (corner.y() * bond.radius)
+        + facing * bond.radius;
+
+    let clip_position = tile * (camera.projection * (quad_position, 1.0));
+    let poly = f.raster.rasterize(clip_position, Cull::Off, index);
+
+    // Points t * ray on the ray through this fragment are a radius from the axis where
+    // |t * ray_across + to_camera| = radius, with ray_across the part of the ray at
+    // right angles to the axis.
+    let ray = poly.lerp(quad_position).normalize();
+    let axis = poly.lerp(axis);
+    let to_camera = poly.lerp(to_camera);
+    let camera_along = poly.lerp(camera_along);
+    let length = poly.lerp(length);
+    let radius = poly.lerp(bond.radius);
+
+    let ray_across = ray - axis * ray.dot(axis);
+    let a = ray_across.dot(ray_across);
+    let b = ray_across.dot(to_camera);
+    let c = to_camera.dot(to_camera) - radius * radius;
+    let discriminant = b * b - a * c;
+    (0.0 - discriminant)
+        .gt(&0.0)
+        .then(|| Any::discard_fragment());
+
+    // the nearer of the two hits
+    let t = (0.0 - b - discriminant.sqrt()) / a;
+    let hit = ray * t;
+
+    // how far along the axis the hit is, which must be on the bond and on this half
+    let along_axis = t * ray.dot(axis) + camera_along;
+    (0.0 - along_axis).gt(&0.0).then(|| Any::discard_fragment());
+    (along_axis - length)
+        .gt(&0.0)
+        .then(|| Any::discard_fragment());
+    let half = poly.lerp(corner.z());
+    ((length * 0.5 - along_axis) * (half * 2.0 - 1.0))
+        .gt(&0.0)
+        .then(|| Any::discard_fragment());
+
+    let hit_normal = (ray_across * t + to_camera).normalize();
+
+    let light = lighting(light_direction(camera.view).dot(hit_normal));
+
+    // the same depth as the atoms give their hits
+    let hit_clip = camera.projection * (hit, 1.0);
+    let depth = hit_clip.z() / hit_clip.w();
+    f.io.depth::<Depth32>()
+        .test_write(DepthTest::Greater, DepthWrite::Write(depth));
+
+    let start_color = poly.lerp(bond.start_color);
+    let end_color = poly.lerp(bond.end_color);
+    let color = start_color + (end_color - start_color) * half;
+    let color = color * light;
+    f.io.color::<RGBA_Surface>().set((color, 1.0));
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+use wgpu::IndexFormat;
+
+use crate::{
+    bond_pipeline::{self, BondCpu, CornerCpu},
+    camera::Camera,
+    glue,
+    gpubuf::GpuBuf,
+    render_pipeline::{CameraCpu, UniformCpu},
+};
+
+/// Draws bonds as cylinders. Meant to be drawn in the same render pass as the atoms,
+/// against the same depth buffer.
+pub struct BondRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    index_buf: GpuBuf<u32>,
+    vertex_buf: GpuBuf<CornerCpu>,
+    instance_buf: GpuBuf<BondCpu>,
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buf: GpuBuf<CameraCpu>,
+    tile_bind_group: wgpu::BindGroup,
+    tile_buf: GpuBuf<UniformCpu>,
+}
+
+impl BondRenderer {
+    pub fn create(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        swapchain_format: wgpu::TextureFormat,
+    ) -> Self {
+        let recording = shame::record_render_pipeline(bond_pipeline::pipeline);
+        let (render_pipeline, bind_group_layouts) =
+            glue::make_render_pipeline(&recording, &device, Some(swapchain_format));
+
+        // a quad for each half of the bond
+        let vertex_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[
+                [0.0, -1.0, 0.0],
+                [1.0, -1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, -1.0, 1.0],
+                [1.0, -1.0, 1.0],
+                [1.0, 1.0, 1.0],
+                [0.0, 1.0, 1.0],
+            ],
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7],
+            wgpu::BufferUsages::INDEX,
+        );
+
+        let instance_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[],
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let uniform_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Camera::default().uniform()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        assert_eq!(bind_group_layouts.len(), 2);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[0],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        let tile_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Mat4::IDENTITY],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[1],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(tile_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        Self {
+            vertex_buf,
+            index_buf,
+            instance_buf,
+            render_pipeline,
+            bind_group,
+            uniform_buf,
+            tile_bind_group,
+            tile_buf,
+            queue,
+            device,
+        }
+    }
+
+    pub fn set_bonds(&mut self, bonds: &[BondCpu]) {
+        self.instance_buf = GpuBuf::initialize(
+            Arc::clone(&self.device),
+            Arc::clone(&self.queue),
+            bonds,
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+    }
+
+    /// Like set_bonds but writes into the existing instance buffer when it is large
+    /// enough. Meant for stepping through trajectory frames.
+    pub fn update_bonds(&mut self, bonds: &[BondCpu]) {
+        if bonds.len() <= self.instance_buf.capacity() {
+            self.instance_buf.copy_from_slice(bonds);
+        } else {
+            self.set_bonds(bonds);
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.uniform_buf.copy_from_slice(&[camera.uniform()]);
+    }
+
+    /// Applied in clip space after everything else, to draw one tile of a larger image.
+    pub fn set_tile(&mut self, tile: Mat4) {
+        self.tile_buf.copy_from_slice(&[tile]);
+    }
+
+    /// write render commands to the command buffer
+    pub fn render<'a: 'b, 'b>(&'a self, pass: &mut wgpu::RenderPass<'b>) {
+        let Some(instance_slice) = self.instance_buf.slice() else {
+            return;
+        };
+
+        pass.set_pipeline(&self.render_pipeline);
+
+        pass.set_index_buffer(self.index_buf.slice().unwrap(), IndexFormat::Uint32);
+        pass.set_vertex_buffer(0, self.vertex_buf.slice().unwrap());
+        pass.set_vertex_buffer(1, instance_slice);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, &self.tile_bind_group, &[]);
+
+        pass.draw_indexed(
+            0..(self.index_buf.len() as u32),
+            0,
+            0..(self.instance_buf.len() as u32),
+        );
+    }
+
+    pub fn need_features() -> wgpu::Features {
+        bond_pipeline::features_used()
+    }
+}
@@ -0,0 +1,504 @@
+//! A binary cache of parsed structures, for reloading huge files without parsing them
+//! again.
+//!
+//! The atoms are stored exactly as [`AtomCpu`], so opening a cache is a memory map and a
+//! checksum, and each model can be uploaded to the GPU straight from the map. Anything
+//! else about the structure lives in side tables next to the atoms.
+//!
+//! Layout, all little endian:
+//!
+//! ```text
+//! 0   magic "BDDATOMS"
+//! 8   version: u32
+//! 12  section count: u32
+//! 16  checksum of everything after the directory: u64
+//! 24  directory, per section: tag [u8; 4], reserved u32, offset u64, length u64
+//!     sections, each starting on an 8 byte boundary
+//! ```
+//!
+//! Sections are `ATOM` (the atoms of every model, one after another), `MODL` (a u64 atom
+//! count per model), `BOND` (pairs of u32 atom indices within a model) and `NAME` (atom
+//! names, one per line). Unknown sections are skipped.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+
+use memmap2::{MmapMut, MmapOptions};
+
+use crate::{pdb::Pdb, render_pipeline::AtomCpu};
+
+const MAGIC: &[u8; 8] = b"BDDATOMS";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 24;
+const DIRECTORY_ENTRY_LEN: usize = 24;
+
+const ATOM: [u8; 4] = *b"ATOM";
+const MODL: [u8; 4] = *b"MODL";
+const BOND: [u8; 4] = *b"BOND";
+const NAME: [u8; 4] = *b"NAME";
+
+/// What a structure has besides its atoms. Both tables may be empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// Pairs of atom indices within a model.
+    pub bonds: Vec<[u32; 2]>,
+    /// One name per atom of a model, such as `A/ALA 12/CA`.
+    pub names: Vec<String>,
+}
+
+impl Topology {
+    /// Bonds and names of the first model.
+    pub fn from_pdb(pdb: &Pdb) -> Self {
+        let Some(model) = pdb.models.first() else {
+            return Self::default();
+        };
+        let index: HashMap<u32, u32> = model
+            .atoms
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (a.serial, i as u32))
+            .collect();
+        Self {
+            bonds: pdb
+                .bonds
+                .iter()
+                .filter_map(|(a, b)| Some([*index.get(a)?, *index.get(b)?]))
+                .collect(),
+            names: model
+                .atoms
+                .iter()
+                .map(|a| format!("{}/{} {}/{}", a.chain_id, a.res_name, a.res_seq, a.name))
+                .collect(),
+        }
+    }
+}
+
+pub struct Cache {
+    /// Copy on write, so the atoms can be changed in memory without touching the file.
+    map: MmapMut,
+    atoms: Range<usize>,
+    /// Atom index ranges of each model.
+    models: Vec<Range<usize>>,
+    pub topology: Topology,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Invalid { offset: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    NotACache,
+    UnsupportedVersion(u32),
+    /// Caches hold atoms in the layout of a little endian machine.
+    BigEndianHost,
+    Truncated,
+    /// The file was changed or damaged after it was written.
+    Checksum {
+        expected: u64,
+        found: u64,
+    },
+    MissingSection([u8; 4]),
+    /// A section whose size or contents do not fit the rest of the file.
+    InvalidSection([u8; 4]),
+}
+
+impl Cache {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::Io)?;
+        // Safety: the mapping is private, so writes never reach the file. Modifying the
+        // file while it is open is undefined behavior, as with any memory mapped file.
+        let map = unsafe { MmapOptions::new().map_copy(&file) }.map_err(Error::Io)?;
+        Self::from_map(map)
+    }
+
+    fn from_map(map: MmapMut) -> Result<Self, Error> {
+        let invalid = |offset, kind| Error::Invalid { offset, kind };
+
+        if cfg!(target_endian = "big") {
+            return Err(invalid(0, ErrorKind::BigEndianHost));
+        }
+        if map.get(..8) != Some(MAGIC) {
+            return Err(invalid(0, ErrorKind::NotACache));
+        }
+        if map.len() < HEADER_LEN {
+            return Err(invalid(map.len(), ErrorKind::Truncated));
+        }
+        let version = u32_at(&map, 8);
+        if version != VERSION {
+            return Err(invalid(8, ErrorKind::UnsupportedVersion(version)));
+        }
+        let section_count = u32_at(&map, 12) as usize;
+        let expected = u64_at(&map, 16);
+
+        let data_start = HEADER_LEN + section_count * DIRECTORY_ENTRY_LEN;
+        if map.len() < data_start {
+            return Err(invalid(map.len(), ErrorKind::Truncated));
+        }
+        let found = checksum(&map[data_start..]);
+        if found != expected {
+            return Err(invalid(16, ErrorKind::Checksum { expected, found }));
+        }
+
+        let mut sections = vec![];
+        for n in 0..section_count {
+            let entry = HEADER_LEN + n * DIRECTORY_ENTRY_LEN;
+            let tag: [u8; 4] = map[entry..entry + 4].try_into().unwrap();
+            let offset = u64_at(&map, entry + 8) as usize;
+            let len = u64_at(&map, entry + 16) as usize;
+            let end = offset.checked_add(len).filter(|&end| end <= map.len());
+            if offset < data_start || !offset.is_multiple_of(8) || end.is_none() {
+                return Err(invalid(entry, ErrorKind::InvalidSection(tag)));
+            }
+            sections.push((tag, offset..offset + len));
+        }
+        let section = |tag| {
+            sections
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, range)| range.clone())
+        };
+
+        let missing = |tag| invalid(HEADER_LEN, ErrorKind::MissingSection(tag));
+        let atoms = section(ATOM).ok_or_else(|| missing(ATOM))?;
+        if !atoms.len().is_multiple_of(std::mem::size_of::<AtomCpu>()) {
+            return Err(invalid(atoms.start, ErrorKind::InvalidSection(ATOM)));
+        }
+        let atom_count = atoms.len() / std::mem::size_of::<AtomCpu>();
+
+        let model_section = section(MODL).ok_or_else(|| missing(MODL))?;
+        let invalid_models = || invalid(model_section.start, ErrorKind::InvalidSection(MODL));
+        let mut models = vec![];
+        let mut start = 0usize;
+        for count in map[model_section.clone()].chunks_exact(8) {
+            let count = u64::from_le_bytes(count.try_into().unwrap()) as usize;
+            let end = start.checked_add(count).ok_or_else(invalid_models)?;
+            models.push(start..end);
+            start = end;
+        }
+        if start != atom_count || !model_section.len().is_multiple_of(8) {
+            return Err(invalid_models());
+        }
+
+        let mut topology = Topology::default();
+        if let Some(bonds) = section(BOND) {
+            topology.bonds = map[bonds.clone()]
+                .chunks_exact(8)
+                .map(|pair| [u32_at(pair, 0), u32_at(pair, 4)])
+                .collect();
+            if !bonds.len().is_multiple_of(8) {
+                return Err(invalid(bonds.start, ErrorKind::InvalidSection(BOND)));
+            }
+        }
+        if let Some(names) = section(NAME) {
+            let text = std::str::from_utf8(&map[names.clone()])
+                .map_err(|_| invalid(names.start, ErrorKind::InvalidSection(NAME)))?;
+            topology.names = text.lines().map(str::to_string).collect();
+        }
+
+        Ok(Self {
+            map,
+            atoms,
+            models,
+            topology,
+        })
+    }
+
+    /// Number of models.
+    pub fn len(&self) -> usize {
+        self.models.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty()
+    }
+
+    /// The atoms of model `n`, ready to upload.
+    pub fn model(&self, n: usize) -> &[AtomCpu] {
+        let atoms: &[AtomCpu] = bytemuck::cast_slice(&self.map[self.atoms.clone()]);
+        &atoms[self.models[n].clone()]
+    }
+
+    /// Changes stay in memory, the file is left as it is.
+    pub fn model_mut(&mut self, n: usize) -> &mut [AtomCpu] {
+        let atoms: &mut [AtomCpu] = bytemuck::cast_slice_mut(&mut self.map[self.atoms.clone()]);
+        &mut atoms[self.models[n].clone()]
+    }
+}
+
+pub fn save(
+    path: impl AsRef<Path>,
+    models: &[Vec<AtomCpu>],
+    topology: &Topology,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write(&mut file, models, topology)?;
+    file.flush()
+}
+
+pub fn write(mut w: impl Write, models: &[Vec<AtomCpu>], topology: &Topology) -> io::Result<()> {
+    // each section is a list of pieces, so the atoms are written without a copy
+    let counts: Vec<u8> = models
+        .iter()
+        .flat_map(|m| (m.len() as u64).to_le_bytes())
+        .collect();
+    let bonds: Vec<u8> = topology
+        .bonds
+        .iter()
+        .flatten()
+        .flat_map(|i| i.to_le_bytes())
+        .collect();
+    let mut names = topology.names.join("\n");
+    if !names.is_empty() {
+        names.push('\n');
+    }
+    let sections: [([u8; 4], Vec<&[u8]>); 4] = [
+        (
+            ATOM,
+            models.iter().map(|m| bytemuck::cast_slice(m)).collect(),
+        ),
+        (MODL, vec![&counts]),
+        (BOND, vec![&bonds]),
+        (NAME, vec![names.as_bytes()]),
+    ];
+
+    let mut directory = vec![];
+    let mut offset = HEADER_LEN + sections.len() * DIRECTORY_ENTRY_LEN;
+    let mut hasher = Checksum::default();
+    for (tag, pieces) in &sections {
+        let len: usize = pieces.iter().map(|p| p.len()).sum();
+        directory.extend_from_slice(tag);
+        directory.extend_from_slice(&0u32.to_le_bytes());
+        directory.extend_from_slice(&(offset as u64).to_le_bytes());
+        directory.extend_from_slice(&(len as u64).to_le_bytes());
+        for piece in pieces {
+            hasher.update(piece);
+        }
+        hasher.update(&[0; 8][..padding(len)]);
+        offset += len + padding(len);
+    }
+
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&(sections.len() as u32).to_le_bytes())?;
+    w.write_all(&hasher.finish().to_le_bytes())?;
+    w.write_all(&directory)?;
+    for (_, pieces) in &sections {
+        let mut len = 0;
+        for piece in pieces {
+            w.write_all(piece)?;
+            len += piece.len();
+        }
+        w.write_all(&[0; 8][..padding(len)])?;
+    }
+    Ok(())
+}
+
+/// Zeros after a section of `len` bytes so the next one starts on an 8 byte boundary.
+fn padding(len: usize) -> usize {
+    len.next_multiple_of(8) - len
+}
+
+fn checksum(data: &[u8]) -> u64 {
+    let mut hasher = Checksum::default();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// FNV-1a taken a 64 bit word at a time rather than a byte at a time, which is fast
+/// enough for gigabytes of atoms. A partial last word is padded with zeros.
+struct Checksum {
+    hash: u64,
+    partial: Vec<u8>,
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self {
+            hash: 0xcbf2_9ce4_8422_2325,
+            partial: vec![],
+        }
+    }
+}
+
+impl Checksum {
+    fn update(&mut self, mut data: &[u8]) {
+        if !self.partial.is_empty() {
+            let take = (8 - self.partial.len()).min(data.len());
+            self.partial.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.partial.len() < 8 {
+                return;
+            }
+            let word = std::mem::take(&mut self.partial);
+            self.mix(&word);
+        }
+        let words = data.chunks_exact(8);
+        self.partial = words.remainder().to_vec();
+        for word in words {
+            self.mix(word);
+        }
+    }
+
+    fn mix(&mut self, word: &[u8]) {
+        self.hash ^= u64::from_le_bytes(word.try_into().unwrap());
+        self.hash = self.hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    fn finish(mut self) -> u64 {
+        if !self.partial.is_empty() {
+            let mut word = std::mem::take(&mut self.partial);
+            word.resize(8, 0);
+            self.mix(&word);
+        }
+        self.hash
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Invalid { offset, kind } => write!(f, "byte {offset}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::NotACache => write!(f, "not a bddatoms cache"),
+            ErrorKind::UnsupportedVersion(v) => write!(f, "unsupported cache version {v}"),
+            ErrorKind::BigEndianHost => {
+                write!(f, "caches can only be read on little endian machines")
+            }
+            ErrorKind::Truncated => write!(f, "file ended early"),
+            ErrorKind::Checksum { expected, found } => write!(
+                f,
+                "checksum is {found:#018x} but should be {expected:#018x}, the file is damaged"
+            ),
+            ErrorKind::MissingSection(tag) => {
+                write!(f, "missing {} section", String::from_utf8_lossy(tag))
+            }
+            ErrorKind::InvalidSection(tag) => {
+                write!(f, "invalid {} section", String::from_utf8_lossy(tag))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(n: usize) -> AtomCpu {
+        AtomCpu {
+            pos: [n as f32, 2.0 * n as f32, -(n as f32)],
+            color: [0.1 * n as f32, 0.5, 1.0],
+            radius: 1.5 + n as f32,
+        }
+    }
+
+    fn open(bytes: &[u8]) -> Result<Cache, Error> {
+        let mut map = MmapMut::map_anon(bytes.len()).unwrap();
+        map.copy_from_slice(bytes);
+        Cache::from_map(map)
+    }
+
+    fn models() -> (Vec<Vec<AtomCpu>>, Topology) {
+        let models = vec![(0..3).map(atom).collect(), (3..6).map(atom).collect()];
+        let topology = Topology {
+            bonds: vec![[0, 1], [1, 2]],
+            names: vec!["A/ALA 1/N".into(), "A/ALA 1/CA".into(), "A/ALA 1/C".into()],
+        };
+        (models, topology)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (models, topology) = models();
+        let mut bytes = vec![];
+        write(&mut bytes, &models, &topology).unwrap();
+        let cache = open(&bytes).unwrap();
+        assert_eq!(cache.len(), 2);
+        for (n, model) in models.iter().enumerate() {
+            let written: &[u8] = bytemuck::cast_slice(model);
+            let read: &[u8] = bytemuck::cast_slice(cache.model(n));
+            assert_eq!(read, written);
+        }
+        assert_eq!(cache.topology, topology);
+    }
+
+    #[test]
+    fn overflowing_model_counts_are_invalid() {
+        let (models, topology) = models();
+        let mut bytes = vec![];
+        write(&mut bytes, &models, &topology).unwrap();
+
+        let section_count = u32_at(&bytes, 12) as usize;
+        let entry = (0..section_count)
+            .map(|n| HEADER_LEN + n * DIRECTORY_ENTRY_LEN)
+            .find(|&entry| bytes[entry..entry + 4] == MODL)
+            .unwrap();
+        let offset = u64_at(&bytes, entry + 8) as usize;
+        bytes[offset..offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        // signed again, so that only the counts are wrong
+        let data_start = HEADER_LEN + section_count * DIRECTORY_ENTRY_LEN;
+        let sum = checksum(&bytes[data_start..]);
+        bytes[16..24].copy_from_slice(&sum.to_le_bytes());
+
+        assert!(matches!(
+            open(&bytes),
+            Err(Error::Invalid {
+                kind: ErrorKind::InvalidSection(MODL),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn damage_is_caught() {
+        let (models, topology) = models();
+        let mut bytes = vec![];
+        write(&mut bytes, &models, &topology).unwrap();
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            open(&bytes),
+            Err(Error::Invalid {
+                kind: ErrorKind::Checksum { .. },
+                ..
+            })
+        ));
+        assert!(matches!(
+            open(&bytes[..bytes.len() / 2]),
+            Err(Error::Invalid { .. })
+        ));
+        assert!(matches!(
+            open(b"not a cache at all, just some text"),
+            Err(Error::Invalid {
+                kind: ErrorKind::NotACache,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+use std::f32::consts::TAU;
+
+use glam::{vec3, Mat4, Vec3};
+
+use crate::render_pipeline::{AtomCpu, CameraCpu};
+
+/// The camera sits this many times the radius of the scene back from its middle, far
+/// enough to see all of it.
+pub const DISTANCE: f32 = 4.0;
+
+/// The smallest radius [`Bounds::of`] gives, in angstroms. A lone atom with no radius
+/// would otherwise put the camera, and both clipping planes, at the atom itself.
+pub const MIN_RADIUS: f32 = 0.01;
+
+/// Where the scene is seen from, and how it is projected onto the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    /// From the scene to view space, where the camera sits at the origin looking down -z.
+    pub view: Mat4,
+    /// The vertical field of view, in radians.
+    pub fov_y: f32,
+    /// The width of the image over its height.
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// The part of the scene the camera keeps in view. Atoms are drawn in their own units,
+/// so the camera moves back for larger structures rather than the atoms shrinking.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        }
+    }
+}
+
+impl Bounds {
+    /// Around every atom, with a little room to spare.
+    pub fn of(atoms: &[AtomCpu]) -> Self {
+        if atoms.is_empty() {
+            return Self::default();
+        }
+        let center = atoms
+            .iter()
+            .fold(Vec3::ZERO, |sum, a| sum + Vec3::from(a.pos))
+            / atoms.len() as f32;
+        let extent = atoms
+            .iter()
+            .map(|a| Vec3::from(a.pos).distance(center) + a.radius)
+            .fold(0.0, f32::max);
+        Self {
+            center,
+            radius: (extent / 0.9).max(MIN_RADIUS),
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        let mut camera = Self {
+            view: Mat4::IDENTITY,
+            // narrow enough that perspective stays gentle
+            fov_y: TAU / 12.0,
+            aspect: 1.0,
+            near: 0.0,
+            far: 0.0,
+        };
+        camera.frame(&Bounds::default(), Mat4::IDENTITY);
+        camera
+    }
+}
+
+impl Camera {
+    /// Look at `bounds` turned by `transform` about its middle, from [`DISTANCE`] times
+    /// its radius away, with the clipping planes spaced to match.
+    pub fn frame(&mut self, bounds: &Bounds, transform: Mat4) {
+        self.view = Mat4::from_translation(vec3(0.0, 0.0, -DISTANCE * bounds.radius))
+            * transform
+            * Mat4::from_translation(-bounds.center);
+        self.near = 0.1 * bounds.radius;
+        self.far = 100.0 * bounds.radius;
+    }
+
+    /// From view space to clip space, with the near plane at depth 1 and the far plane
+    /// at 0, so that larger depth is closer, as the shaders test for.
+    pub fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fov_y, self.aspect, self.far, self.near)
+    }
+
+    /// As the shaders see it.
+    pub fn uniform(&self) -> CameraCpu {
+        CameraCpu {
+            view: self.view,
+            projection: self.projection(),
+        }
+    }
+}
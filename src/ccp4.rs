@@ -0,0 +1,319 @@
+//! Reader for CCP4 and MRC density maps, as written by crystallographic and cryo-EM
+//! software.
+//!
+//! A 1024 byte header describes the unit cell, which file axis runs along which cell
+//! axis, and where the stored box sits. The voxels are reordered into x, y, z order
+//! and placed in Cartesian space, so a map lines up with the model it was computed
+//! from. Only the box stored in the file is read; maps covering part of the cell are
+//! not expanded by symmetry.
+
+use std::{fmt, io, path::Path};
+
+use crate::{smcif::Cell, volume::Grid};
+
+const HEADER_LEN: usize = 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct Map {
+    pub grid: Grid,
+    pub cell: Cell,
+    pub space_group: i32,
+    /// Mean and root mean square deviation of the voxel values. Computed rather than
+    /// taken from the header, which is not always kept up to date.
+    pub mean: f32,
+    pub rms: f32,
+    pub labels: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Invalid { offset: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Truncated,
+    /// Only signed bytes, 16 bit integers and 32 bit floats are supported.
+    UnsupportedMode(i32),
+    /// The column, row and section axes are not a permutation of x, y and z.
+    AxisOrder([i32; 3]),
+    InvalidDimensions([i32; 3]),
+}
+
+impl Map {
+    /// The value `sigma` standard deviations above the mean, for contouring.
+    pub fn sigma_level(&self, sigma: f32) -> f32 {
+        self.mean + sigma * self.rms
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Map, Error> {
+    parse(&std::fs::read(path).map_err(Error::Io)?)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Map, Error> {
+    let invalid = |offset, kind| Error::Invalid { offset, kind };
+    if bytes.len() < HEADER_LEN {
+        return Err(invalid(bytes.len(), ErrorKind::Truncated));
+    }
+
+    // the machine stamp gives the byte order, 0x44 for little endian and 0x11 for
+    // big endian. Old files may leave it blank, in which case they are little endian.
+    let big_endian = bytes[212] == 0x11;
+    let word = |n: usize| -> [u8; 4] {
+        let mut word: [u8; 4] = bytes[n * 4..n * 4 + 4].try_into().unwrap();
+        if big_endian {
+            word.reverse();
+        }
+        word
+    };
+    let int = |n| i32::from_le_bytes(word(n));
+    let float = |n| f32::from_le_bytes(word(n));
+
+    // columns, rows and sections, fastest varying first
+    let counts = [int(0), int(1), int(2)];
+    let mode = int(3);
+    let start = [int(4), int(5), int(6)];
+    // grid sampling along the cell edges
+    let intervals = [int(7), int(8), int(9)];
+    let cell = Cell {
+        a: float(10),
+        b: float(11),
+        c: float(12),
+        alpha: float(13),
+        beta: float(14),
+        gamma: float(15),
+    };
+    // which cell axis the columns, rows and sections run along, 1 based
+    let axis_order = [int(16), int(17), int(18)];
+    let space_group = int(22);
+    let symmetry_len = int(23).max(0) as usize;
+    let origin = [float(49), float(50), float(51)];
+    let labels = (0..int(55).clamp(0, 10) as usize)
+        .map(|i| {
+            let label = &bytes[224 + i * 80..224 + (i + 1) * 80];
+            String::from_utf8_lossy(label)
+                .trim_end_matches(['\0', ' '])
+                .to_string()
+        })
+        .collect();
+
+    if counts.iter().any(|&n| n <= 0) {
+        return Err(invalid(0, ErrorKind::InvalidDimensions(counts)));
+    }
+    if intervals.iter().any(|&n| n <= 0) {
+        return Err(invalid(28, ErrorKind::InvalidDimensions(intervals)));
+    }
+    let mut sorted = axis_order;
+    sorted.sort();
+    if sorted != [1, 2, 3] {
+        return Err(invalid(64, ErrorKind::AxisOrder(axis_order)));
+    }
+    let voxel_len = match mode {
+        0 => 1,
+        1 => 2,
+        2 => 4,
+        _ => return Err(invalid(12, ErrorKind::UnsupportedMode(mode))),
+    };
+
+    // a damaged header can ask for more voxels than there are addresses
+    let too_large = || invalid(0, ErrorKind::InvalidDimensions(counts));
+    let points = counts
+        .iter()
+        .try_fold(1usize, |points, &n| points.checked_mul(n as usize))
+        .ok_or_else(too_large)?;
+    let data_len = points.checked_mul(voxel_len).ok_or_else(too_large)?;
+    let counts = counts.map(|n| n as usize);
+    let truncated = || invalid(bytes.len(), ErrorKind::Truncated);
+    let data_start = HEADER_LEN.checked_add(symmetry_len).ok_or_else(truncated)?;
+    let data_end = data_start.checked_add(data_len).ok_or_else(truncated)?;
+    let data = bytes.get(data_start..data_end).ok_or_else(truncated)?;
+    let voxel = |i: usize| -> f32 {
+        let mut v = [0; 4];
+        v[..voxel_len].copy_from_slice(&data[i * voxel_len..(i + 1) * voxel_len]);
+        if big_endian {
+            v[..voxel_len].reverse();
+        }
+        match mode {
+            0 => v[0] as i8 as f32,
+            1 => i16::from_le_bytes([v[0], v[1]]) as f32,
+            _ => f32::from_le_bytes(v),
+        }
+    };
+
+    // file axis for each of x, y and z
+    let file_axis: [usize; 3] = std::array::from_fn(|xyz| {
+        axis_order
+            .iter()
+            .position(|&a| a as usize == xyz + 1)
+            .unwrap()
+    });
+    let shape = file_axis.map(|f| counts[f]);
+    let mut values = Vec::with_capacity(points);
+    for x in 0..shape[0] {
+        for y in 0..shape[1] {
+            for z in 0..shape[2] {
+                let mut at = [0; 3];
+                at[file_axis[0]] = x;
+                at[file_axis[1]] = y;
+                at[file_axis[2]] = z;
+                values.push(voxel(at[0] + counts[0] * (at[1] + counts[1] * at[2])));
+            }
+        }
+    }
+
+    // CCP4 maps place the box with the start indices, MRC maps usually with the origin
+    // and zero starts. Honouring both covers either convention.
+    let vectors = cell.vectors();
+    let axes: [[f32; 3]; 3] = std::array::from_fn(|a| vectors[a].map(|v| v / intervals[a] as f32));
+    let start = file_axis.map(|f| start[f]);
+    let origin: [f32; 3] = std::array::from_fn(|i| {
+        origin[i] + (0..3).map(|a| axes[a][i] * start[a] as f32).sum::<f32>()
+    });
+
+    let grid = Grid {
+        shape,
+        origin,
+        axes,
+        values,
+    };
+    let (mean, rms) = grid.statistics();
+    Ok(Map {
+        grid,
+        cell,
+        space_group,
+        mean,
+        rms,
+        labels,
+    })
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Invalid { offset, kind } => write!(f, "byte {offset}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Truncated => write!(f, "file is truncated"),
+            ErrorKind::UnsupportedMode(mode) => write!(f, "unsupported data mode {mode}"),
+            ErrorKind::AxisOrder(order) => write!(f, "invalid axis order {order:?}"),
+            ErrorKind::InvalidDimensions(n) => write!(f, "invalid grid dimensions {n:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2 by 3 by 4 map of floats whose columns run along z, rows along x and sections
+    /// along y. Each voxel holds its index in the file.
+    fn write(big_endian: bool) -> Vec<u8> {
+        let mut header = [0u32; HEADER_LEN / 4];
+        let ints = [2, 3, 4, 2, 1, 0, 2, 10, 20, 30];
+        for (word, v) in header.iter_mut().zip(ints) {
+            *word = v as u32;
+        }
+        for (n, v) in [10.0f32, 20.0, 30.0, 90.0, 90.0, 90.0]
+            .into_iter()
+            .enumerate()
+        {
+            header[10 + n] = v.to_bits();
+        }
+        header[16..19].copy_from_slice(&[3, 1, 2]);
+        header[22] = 1;
+        for (n, v) in [0.5f32, 0.25, -1.0].into_iter().enumerate() {
+            header[49 + n] = v.to_bits();
+        }
+        let words = header
+            .into_iter()
+            .chain((0..24).map(|i| (i as f32).to_bits()));
+
+        let mut bytes: Vec<u8> = words
+            .flat_map(|w| {
+                if big_endian {
+                    w.to_be_bytes()
+                } else {
+                    w.to_le_bytes()
+                }
+            })
+            .collect();
+        // the stamp is bytes, written the same in either order
+        bytes[212..216].copy_from_slice(if big_endian {
+            &[0x11, 0x11, 0, 0]
+        } else {
+            &[0x44, 0x41, 0, 0]
+        });
+        bytes
+    }
+
+    #[test]
+    fn axes_are_reordered_and_placed() {
+        for big_endian in [false, true] {
+            let map = parse(&write(big_endian)).unwrap();
+            let grid = &map.grid;
+            // x runs along rows, y along sections and z along columns
+            assert_eq!(grid.shape, [3, 4, 2]);
+            for x in 0..3 {
+                for y in 0..4 {
+                    for z in 0..2 {
+                        let file_index = z + 2 * (x + 3 * y);
+                        assert_eq!(grid.get([x, y, z]), file_index as f32);
+                    }
+                }
+            }
+            // one angstrom per interval, moved by the start indices and the origin
+            let origin = grid.position([0, 0, 0]).to_array();
+            for (found, expected) in origin.iter().zip([0.5, 2.25, 0.0]) {
+                assert!((found - expected).abs() < 1e-5, "{origin:?}");
+            }
+            let step = grid.position([1, 1, 1]) - grid.position([0, 0, 0]);
+            assert!((step - glam::Vec3::ONE).abs().max_element() < 1e-5);
+            assert_eq!(map.space_group, 1);
+            assert!((map.mean - 11.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn damaged_headers_are_errors() {
+        let bytes = write(false);
+        assert!(matches!(
+            parse(&bytes[..bytes.len() - 1]),
+            Err(Error::Invalid {
+                kind: ErrorKind::Truncated,
+                ..
+            })
+        ));
+
+        let mut huge = bytes.clone();
+        for n in 0..3 {
+            huge[n * 4..n * 4 + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+        }
+        assert!(matches!(
+            parse(&huge),
+            Err(Error::Invalid {
+                kind: ErrorKind::InvalidDimensions(_),
+                ..
+            })
+        ));
+
+        let mut order = bytes;
+        order[64..68].copy_from_slice(&1i32.to_le_bytes());
+        assert!(matches!(
+            parse(&order),
+            Err(Error::Invalid {
+                kind: ErrorKind::AxisOrder([1, 1, 2]),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+//! Tokenizer for the Crystallographic Information File syntax (CIF 1.1), shared by the
+//! mmCIF and small-molecule readers.
+
+use std::{fmt, io};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token<'a> {
+    Data(&'a str),
+    Save(&'a str),
+    Loop,
+    Global,
+    Stop,
+    Tag(&'a str),
+    Value(Value<'a>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Value<'a> {
+    /// Quotes and text field delimiters are not included.
+    pub text: &'a str,
+    pub quoted: bool,
+}
+
+impl<'a> Value<'a> {
+    /// An unquoted `.` (inapplicable) or `?` (unknown).
+    pub fn is_null(&self) -> bool {
+        !self.quoted && (self.text == "." || self.text == "?")
+    }
+
+    pub fn non_null(self) -> Option<&'a str> {
+        (!self.is_null()).then_some(self.text)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    UnterminatedQuote,
+    UnterminatedTextField,
+    /// The number of values in a loop is not a multiple of the number of tags.
+    RaggedLoop,
+    MissingTag(&'static str),
+    /// A loop mixes tags from different categories.
+    ForeignTag(String),
+    InvalidValue {
+        tag: &'static str,
+        value: String,
+    },
+}
+
+pub struct Tokenizer<'a> {
+    src: &'a str,
+    pos: usize,
+    cursor_line: usize,
+    line: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(src: &'a str) -> Self {
+        Self {
+            src,
+            pos: 0,
+            cursor_line: 1,
+            line: 1,
+        }
+    }
+
+    /// Line on which the most recently returned token starts.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.pos == 0 || self.src.as_bytes()[self.pos - 1] == b'\n'
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        let bytes = self.src.as_bytes();
+        while let Some(&b) = bytes.get(self.pos) {
+            match b {
+                b'\n' => {
+                    self.cursor_line += 1;
+                    self.pos += 1;
+                }
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                b'#' => {
+                    self.pos = self.src[self.pos..]
+                        .find('\n')
+                        .map_or(self.src.len(), |i| self.pos + i);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn text_field(&mut self) -> Result<Value<'a>, Error> {
+        let start = self.pos + 1;
+        let end = self.src[start..]
+            .find("\n;")
+            .map(|i| start + i)
+            .ok_or_else(|| self.error(ErrorKind::UnterminatedTextField))?;
+        let text = &self.src[start..end];
+        self.cursor_line += text.matches('\n').count() + 1;
+        self.pos = end + 2;
+        Ok(Value {
+            text: text.strip_suffix('\r').unwrap_or(text),
+            quoted: true,
+        })
+    }
+
+    fn quoted(&mut self, quote: u8) -> Result<Value<'a>, Error> {
+        let bytes = self.src.as_bytes();
+        let start = self.pos + 1;
+        let mut i = start;
+        // a quote only closes the string when followed by whitespace
+        loop {
+            match bytes.get(i) {
+                None | Some(b'\n') | Some(b'\r') => {
+                    return Err(self.error(ErrorKind::UnterminatedQuote))
+                }
+                Some(&b) if b == quote => match bytes.get(i + 1) {
+                    None | Some(b' ' | b'\t' | b'\r' | b'\n') => break,
+                    _ => {}
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+        self.pos = i + 1;
+        Ok(Value {
+            text: &self.src[start..i],
+            quoted: true,
+        })
+    }
+
+    fn bare(&mut self) -> Token<'a> {
+        let start = self.pos;
+        let len = self.src[start..]
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(self.src.len() - start);
+        self.pos += len;
+        let word = &self.src[start..self.pos];
+
+        let reserved = |prefix: &str| {
+            word.get(..prefix.len())
+                .filter(|p| p.eq_ignore_ascii_case(prefix))
+                .map(|_| &word[prefix.len()..])
+        };
+        if let Some(name) = reserved("data_") {
+            Token::Data(name)
+        } else if let Some(name) = reserved("save_") {
+            Token::Save(name)
+        } else if word.eq_ignore_ascii_case("loop_") {
+            Token::Loop
+        } else if word.eq_ignore_ascii_case("global_") {
+            Token::Global
+        } else if word.eq_ignore_ascii_case("stop_") {
+            Token::Stop
+        } else if word.starts_with('_') {
+            Token::Tag(word)
+        } else {
+            Token::Value(Value {
+                text: word,
+                quoted: false,
+            })
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_whitespace_and_comments();
+        self.line = self.cursor_line;
+        let token = match *self.src.as_bytes().get(self.pos)? {
+            b';' if self.at_line_start() => self.text_field().map(Token::Value),
+            q @ (b'\'' | b'"') => self.quoted(q).map(Token::Value),
+            _ => Ok(self.bare()),
+        };
+        Some(token)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnterminatedQuote => write!(f, "unterminated quoted string"),
+            ErrorKind::UnterminatedTextField => write!(f, "unterminated text field"),
+            ErrorKind::RaggedLoop => {
+                write!(
+                    f,
+                    "number of loop values is not a multiple of the number of tags"
+                )
+            }
+            ErrorKind::MissingTag(tag) => write!(f, "missing {tag}"),
+            ErrorKind::ForeignTag(tag) => write!(f, "{tag} in a loop of another category"),
+            ErrorKind::InvalidValue { tag, value } => {
+                write!(f, "invalid value for {tag}: {value:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
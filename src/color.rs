@@ -0,0 +1,47 @@
+/// Maps scalars onto a red-white-blue ramp. Values at or below `min` are red, the
+/// midpoint is white and values at or above `max` are blue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Diverging {
+    pub min: f32,
+    pub max: f32,
+}
+
+const RED: [f32; 3] = [0.8, 0.1, 0.1];
+const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+const BLUE: [f32; 3] = [0.1, 0.2, 0.8];
+
+impl Diverging {
+    /// A range centered on zero, as is usual for charges.
+    pub fn symmetric(limit: f32) -> Self {
+        Self {
+            min: -limit,
+            max: limit,
+        }
+    }
+
+    pub fn color(&self, value: f32) -> [f32; 3] {
+        if self.min == self.max {
+            return WHITE;
+        }
+        let t = ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+        if t < 0.5 {
+            lerp(RED, WHITE, t * 2.0)
+        } else {
+            lerp(WHITE, BLUE, t * 2.0 - 1.0)
+        }
+    }
+}
+
+impl Default for Diverging {
+    fn default() -> Self {
+        Self::symmetric(1.0)
+    }
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
@@ -0,0 +1,63 @@
+use shame::prelude::*;
+
+use crate::render_pipeline::CameraGpu;
+
+/// x runs along the segment from 0 to 1, y across it from -1 to 1.
+pub type CornerCpu = [f32; 2];
+
+type CornerGpu = float2;
+
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SegmentCpu {
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub color: [f32; 3],
+}
+
+#[derive(shame::Fields)]
+struct SegmentGpu {
+    start: float3,
+    end: float3,
+    color: float3,
+}
+
+type UniformGpu = float4x4;
+
+/// Half the width of a line, in normalized device coordinates.
+const HALF_WIDTH: f32 = 0.002;
+
+pub fn features_used() -> wgpu::Features {
+    wgpu::Features::DEPTH_CLIP_CONTROL
+}
+
+/// Lines drawn as thin quads facing the screen, since only triangles can be rasterized.
+pub fn pipeline(mut f: RenderFeatures) {
+    let index: TriangleStrip<u32> = f.io.index_buffer();
+
+    let corner: CornerGpu = f.io.vertex_buffer();
+    let segment: SegmentGpu = f.io.instance_buffer();
+    let camera: CameraGpu = f.io.group().uniform_block();
+    // applied last, like for the atoms, so lines keep their width in every tile
+    let tile: UniformGpu = f.io.group().uniform_block();
+
+    let start = camera.projection * (camera.view * (segment.start, 1.0));
+    let end = camera.projection * (camera.view * (segment.end, 1.0));
+
+    // the direction on screen, after the perspective divide
+    let along = (end.xy() / end.w() - start.xy() / start.w()).normalize();
+    let across = (0.0 - along.y(), along.x()).rec();
+
+    // scaled by w so that the divide leaves lines the same width near and far
+    let point = start + (end - start) * corner.x();
+    let clip_position = tile * (point + (across * corner.y() * HALF_WIDTH * point.w(), 0.0, 0.0));
+    let poly = f.raster.rasterize(clip_position, Cull::Off, index);
+
+    // same depth convention as the atom impostors
+    let depth = poly.lerp(point.z()) / poly.lerp(point.w());
+    f.io.depth::<Depth32>()
+        .test_write(DepthTest::Greater, DepthWrite::Write(depth));
+
+    let color = poly.lerp(segment.color);
+    f.io.color::<RGBA_Surface>().set((color, 1.0));
+}
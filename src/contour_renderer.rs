@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+use wgpu::IndexFormat;
+
+use crate::{
+    camera::Camera,
+    contour_pipeline::{self, CornerCpu, SegmentCpu},
+    glue,
+    gpubuf::GpuBuf,
+    render_pipeline::{CameraCpu, UniformCpu},
+    volume::Grid,
+};
+
+/// Draws a density map as a wireframe mesh around a center point, the way model
+/// building programs do. The mesh is recomputed whenever the center moves.
+pub struct ContourRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    index_buf: GpuBuf<u32>,
+    vertex_buf: GpuBuf<CornerCpu>,
+    instance_buf: GpuBuf<SegmentCpu>,
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buf: GpuBuf<CameraCpu>,
+    tile_bind_group: wgpu::BindGroup,
+    tile_buf: GpuBuf<UniformCpu>,
+    map: Option<Grid>,
+    level: f32,
+    center: [f32; 3],
+    radius: f32,
+    color: [f32; 3],
+}
+
+impl ContourRenderer {
+    pub fn create(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        swapchain_format: wgpu::TextureFormat,
+    ) -> Self {
+        let recording = shame::record_render_pipeline(contour_pipeline::pipeline);
+        let (render_pipeline, bind_group_layouts) =
+            glue::make_render_pipeline(&recording, &device, Some(swapchain_format));
+
+        let vertex_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[[0.0, -1.0], [1.0, -1.0], [1.0, 1.0], [0.0, 1.0]],
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[0, 1, 2, 3, 0],
+            wgpu::BufferUsages::INDEX,
+        );
+
+        let instance_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[],
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let uniform_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Camera::default().uniform()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        assert_eq!(bind_group_layouts.len(), 2);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[0],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        let tile_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Mat4::IDENTITY],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[1],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(tile_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        Self {
+            vertex_buf,
+            index_buf,
+            instance_buf,
+            render_pipeline,
+            bind_group,
+            uniform_buf,
+            tile_bind_group,
+            tile_buf,
+            map: None,
+            level: 0.0,
+            center: [0.0; 3],
+            radius: 0.0,
+            color: [0.3, 0.5, 1.0],
+            queue,
+            device,
+        }
+    }
+
+    /// Contour `map` at `level`, in the units of the map, within `radius` of the
+    /// current center.
+    pub fn set_map(&mut self, map: Grid, level: f32, radius: f32) {
+        self.map = Some(map);
+        self.level = level;
+        self.radius = radius;
+        self.contour();
+    }
+
+    pub fn set_level(&mut self, level: f32) {
+        self.level = level;
+        self.contour();
+    }
+
+    pub fn set_center(&mut self, center: [f32; 3]) {
+        self.center = center;
+        self.contour();
+    }
+
+    pub fn set_color(&mut self, color: [f32; 3]) {
+        self.color = color;
+        self.contour();
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        self.color
+    }
+
+    fn contour(&mut self) {
+        let Some(map) = &self.map else {
+            return;
+        };
+        let mesh = map.isosurface_near(self.level, self.center, self.radius);
+        let segments: Vec<SegmentCpu> = mesh
+            .edges()
+            .into_iter()
+            .map(|[a, b]| SegmentCpu {
+                start: mesh.positions[a as usize],
+                end: mesh.positions[b as usize],
+                color: self.color,
+            })
+            .collect();
+
+        if segments.len() <= self.instance_buf.capacity() {
+            self.instance_buf.copy_from_slice(&segments);
+        } else {
+            self.instance_buf = GpuBuf::initialize(
+                Arc::clone(&self.device),
+                Arc::clone(&self.queue),
+                &segments,
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            );
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.uniform_buf.copy_from_slice(&[camera.uniform()]);
+    }
+
+    /// Applied in clip space after everything else, to draw one tile of a larger image.
+    pub fn set_tile(&mut self, tile: Mat4) {
+        self.tile_buf.copy_from_slice(&[tile]);
+    }
+
+    /// write render commands to the command buffer
+    pub fn render<'a: 'b, 'b>(&'a self, pass: &mut wgpu::RenderPass<'b>) {
+        let Some(instance_slice) = self.instance_buf.slice() else {
+            return;
+        };
+
+        pass.set_pipeline(&self.render_pipeline);
+
+        pass.set_index_buffer(self.index_buf.slice().unwrap(), IndexFormat::Uint32);
+        pass.set_vertex_buffer(0, self.vertex_buf.slice().unwrap());
+        pass.set_vertex_buffer(1, instance_slice);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, &self.tile_bind_group, &[]);
+
+        pass.draw_indexed(
+            0..(self.index_buf.len() as u32),
+            0,
+            0..(self.instance_buf.len() as u32),
+        );
+    }
+
+    pub fn need_features() -> wgpu::Features {
+        contour_pipeline::features_used()
+    }
+}
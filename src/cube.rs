@@ -0,0 +1,238 @@
+//! Reader for Gaussian cube files: a molecule followed by one or more scalar fields,
+//! such as orbitals or densities, sampled on a regular grid.
+//!
+//! Cube files are in bohr unless the grid counts are negative. Everything here is
+//! converted to angstroms to match the other readers.
+
+use std::{fmt, io, path::Path};
+
+use crate::{element, render_pipeline::AtomCpu, volume::Grid};
+
+const ANGSTROMS_PER_BOHR: f32 = 0.529_177_2;
+
+#[derive(Clone, Debug, Default)]
+pub struct Cube {
+    /// The two comment lines at the top of the file.
+    pub comments: [String; 2],
+    pub atoms: Vec<Atom>,
+    /// Orbital numbers for files holding several orbitals, empty otherwise.
+    pub orbitals: Vec<i32>,
+    /// One grid per value stored at each point, usually just one.
+    pub grids: Vec<Grid>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub number: u8,
+    pub charge: f32,
+    /// angstroms
+    pub pos: [f32; 3],
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The file ended before the header or grid data was complete.
+    Truncated,
+    /// A header or atom line with fewer fields than the format requires.
+    MissingFields,
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl Cube {
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms.iter().map(Atom::to_atom_cpu).collect()
+    }
+}
+
+impl Atom {
+    pub fn element(&self) -> &'static element::Element {
+        element::by_number(self.number).unwrap_or(&element::UNKNOWN)
+    }
+
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = self.element();
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Cube, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+}
+
+pub fn parse(src: &str) -> Result<Cube, Error> {
+    let mut lines = Lines {
+        inner: src.lines(),
+        line: 0,
+    };
+    let mut cube = Cube {
+        comments: [lines.next()?.to_string(), lines.next()?.to_string()],
+        ..Default::default()
+    };
+
+    // atom count, origin and, optionally, the number of values per point
+    let header = lines.fields(4)?;
+    let count: i64 = lines.number(header[0], "atom count")?;
+    let mut origin = [0.0; 3];
+    for (o, s) in origin.iter_mut().zip(&header[1..4]) {
+        *o = lines.number(s, "origin")?;
+    }
+    let mut values_per_point = match header.get(4) {
+        Some(s) => lines.number(s, "values per point")?,
+        None => 1,
+    };
+
+    let mut shape = [0; 3];
+    let mut axes = [[0.0; 3]; 3];
+    let mut scale = ANGSTROMS_PER_BOHR;
+    for axis in 0..3 {
+        let fields = lines.fields(4)?;
+        let n: i64 = lines.number(fields[0], "point count")?;
+        // a negative count means the file is in angstroms already
+        if n < 0 {
+            scale = 1.0;
+        }
+        shape[axis] = n.unsigned_abs() as usize;
+        for (v, s) in axes[axis].iter_mut().zip(&fields[1..4]) {
+            *v = lines.number(s, "axis")?;
+        }
+    }
+
+    for _ in 0..count.unsigned_abs() {
+        let fields = lines.fields(5)?;
+        let mut pos = [0.0; 3];
+        for (p, s) in pos.iter_mut().zip(&fields[2..5]) {
+            *p = lines.number::<f32>(s, "position")? * scale;
+        }
+        cube.atoms.push(Atom {
+            number: lines.number(fields[0], "atomic number")?,
+            charge: lines.number(fields[1], "charge")?,
+            pos,
+        });
+    }
+
+    // a negative atom count means orbital numbers follow the atoms, as a count and then
+    // the numbers, which may wrap onto more lines
+    let mut values = lines.values();
+    if count < 0 {
+        let n: usize = values.next_number("orbital count")?;
+        for _ in 0..n {
+            cube.orbitals.push(values.next_number("orbital number")?);
+        }
+        values_per_point = n.max(1);
+    }
+
+    let points = shape.iter().product::<usize>();
+    let grid = Grid {
+        shape,
+        origin: origin.map(|o| o * scale),
+        axes: axes.map(|a| a.map(|v| v * scale)),
+        values: Vec::with_capacity(points),
+    };
+    cube.grids = vec![grid; values_per_point];
+    for _ in 0..points {
+        for grid in &mut cube.grids {
+            grid.values.push(values.next_number("value")?);
+        }
+    }
+    Ok(cube)
+}
+
+/// Lines of the file, counted for error messages.
+struct Lines<'a> {
+    inner: std::str::Lines<'a>,
+    line: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn next(&mut self) -> Result<&'a str, Error> {
+        self.line += 1;
+        self.inner
+            .next()
+            .ok_or_else(|| self.error(ErrorKind::Truncated))
+    }
+
+    /// The whitespace separated fields of the next line, at least `min` of them.
+    fn fields(&mut self, min: usize) -> Result<Vec<&'a str>, Error> {
+        let fields: Vec<&str> = self.next()?.split_whitespace().collect();
+        if fields.len() < min {
+            return Err(self.error(ErrorKind::MissingFields));
+        }
+        Ok(fields)
+    }
+
+    fn number<T: std::str::FromStr>(&self, s: &str, field: &'static str) -> Result<T, Error> {
+        s.parse().map_err(|_| {
+            self.error(ErrorKind::InvalidField {
+                field,
+                value: s.to_string(),
+            })
+        })
+    }
+
+    /// Everything after the header is free-form, so read it as a stream of fields.
+    fn values(self) -> Values<'a> {
+        Values {
+            lines: self,
+            fields: "".split_whitespace(),
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+}
+
+struct Values<'a> {
+    lines: Lines<'a>,
+    fields: std::str::SplitWhitespace<'a>,
+}
+
+impl Values<'_> {
+    fn next_number<T: std::str::FromStr>(&mut self, field: &'static str) -> Result<T, Error> {
+        loop {
+            if let Some(s) = self.fields.next() {
+                return self.lines.number(s, field);
+            }
+            self.fields = self.lines.next()?.split_whitespace();
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Truncated => write!(f, "file ended early"),
+            ErrorKind::MissingFields => write!(f, "line has too few fields"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,435 @@
+//! Random access reader for CHARMM/NAMD DCD trajectories.
+//!
+//! The file is memory mapped and frames are located by offset, so opening a trajectory
+//! of any size is cheap and only the frames that are looked at get paged in. Both byte
+//! orders are accepted. DCD stores positions only; colors and radii have to come from
+//! a topology loaded separately.
+
+use std::{fmt, fs::File, io, path::Path};
+
+use memmap2::Mmap;
+
+use crate::render_pipeline::AtomCpu;
+
+pub struct Dcd {
+    map: Mmap,
+    big_endian: bool,
+    atoms: usize,
+    frames: usize,
+    /// byte offset of the first frame
+    first_frame: usize,
+    /// size of each frame in bytes, including record markers
+    frame_len: usize,
+    has_unit_cell: bool,
+    pub title: String,
+    /// Index of the first saved step.
+    pub start_step: i32,
+    /// Number of simulation steps between saved frames.
+    pub step_interval: i32,
+    /// Timestep in AKMA units, zero when the writer did not record it.
+    pub timestep: f32,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Invalid { offset: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The first record is not a `CORD` header in either byte order.
+    NotDcd,
+    /// Fortran record markers disagree with the expected record size.
+    RecordMarker {
+        expected: usize,
+        found: u32,
+    },
+    /// Trajectories with fixed atoms store a different number of atoms per frame,
+    /// which is not supported.
+    FixedAtoms,
+    Truncated,
+    NoSuchFrame(usize),
+    /// Frames are read into as many atoms as the trajectory has.
+    AtomCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Dcd {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::Io)?;
+        // Safety: the mapping is read only. Modifying the file while it is open is
+        // undefined behavior, as with any memory mapped file.
+        let map = unsafe { Mmap::map(&file) }.map_err(Error::Io)?;
+        Self::from_map(map)
+    }
+
+    fn from_map(map: Mmap) -> Result<Self, Error> {
+        let invalid = |offset, kind| Error::Invalid { offset, kind };
+
+        let big_endian = match map.get(..8) {
+            Some([84, 0, 0, 0, b'C', b'O', b'R', b'D']) => false,
+            Some([0, 0, 0, 84, b'C', b'O', b'R', b'D']) => true,
+            _ => return Err(invalid(0, ErrorKind::NotDcd)),
+        };
+        let mut dcd = Self {
+            map,
+            big_endian,
+            atoms: 0,
+            frames: 0,
+            first_frame: 0,
+            frame_len: 0,
+            has_unit_cell: false,
+            title: String::new(),
+            start_step: 0,
+            step_interval: 0,
+            timestep: 0.0,
+        };
+
+        // header: "CORD" followed by 20 control integers
+        let header = dcd.record(0, Some(84))?;
+        let control: [i32; 20] = std::array::from_fn(|i| dcd.i32_at(header.start + 4 + i * 4));
+        let charmm = control[19] != 0;
+        dcd.start_step = control[1];
+        dcd.step_interval = control[2];
+        if charmm {
+            dcd.timestep = f32::from_bits(control[9] as u32);
+            dcd.has_unit_cell = control[10] != 0;
+        }
+        if control[8] != 0 {
+            return Err(invalid(header.start, ErrorKind::FixedAtoms));
+        }
+        let has_4d = charmm && control[11] != 0;
+
+        // title: a count followed by that many 80 character lines
+        let title = dcd.record(header.end + 4, None)?;
+        let lines = dcd.i32_at(title.start).max(0) as usize;
+        dcd.title = (0..lines)
+            .filter_map(|i| {
+                dcd.map
+                    .get(title.start + 4 + i * 80..title.start + 4 + (i + 1) * 80)
+            })
+            .map(|line| {
+                String::from_utf8_lossy(line)
+                    .trim_end_matches(['\0', ' '])
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let atom_count = dcd.record(title.end + 4, Some(4))?;
+        dcd.atoms = dcd.i32_at(atom_count.start).max(0) as usize;
+        dcd.first_frame = atom_count.end + 4;
+
+        let coordinates = 3 + has_4d as usize;
+        dcd.frame_len = dcd.has_unit_cell as usize * (48 + 8) + coordinates * (dcd.atoms * 4 + 8);
+
+        // trust the file size over the header, which is stale for runs still in progress
+        let available = (dcd.map.len() - dcd.first_frame) / dcd.frame_len.max(1);
+        dcd.frames = match usize::try_from(control[0]) {
+            Ok(declared) if declared > 0 => declared.min(available),
+            _ => available,
+        };
+
+        Ok(dcd)
+    }
+
+    pub fn atom_count(&self) -> usize {
+        self.atoms
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    /// Positions of frame `n` in angstroms.
+    pub fn positions(&self, n: usize) -> Result<Vec<[f32; 3]>, Error> {
+        let mut positions = vec![[0.0; 3]; self.atoms];
+        self.read_positions(n, &mut positions)?;
+        Ok(positions)
+    }
+
+    /// Like [`Dcd::positions`] but writes into `out`, which must hold `atom_count`
+    /// positions. Avoids allocating when stepping through frames.
+    pub fn read_positions(&self, n: usize, out: &mut [[f32; 3]]) -> Result<(), Error> {
+        self.read_coordinates(n, out.len(), |i, axis, v| out[i][axis] = v)
+    }
+
+    /// Overwrite the positions of `atoms` with frame `n`, keeping colors and radii.
+    pub fn read_atoms(&self, n: usize, atoms: &mut [AtomCpu]) -> Result<(), Error> {
+        self.read_coordinates(n, atoms.len(), |i, axis, v| atoms[i].pos[axis] = v)
+    }
+
+    /// Pass each coordinate of frame `n` to `set`, once `len` is known to match.
+    fn read_coordinates(
+        &self,
+        n: usize,
+        len: usize,
+        mut set: impl FnMut(usize, usize, f32),
+    ) -> Result<(), Error> {
+        let mut offset = self.frame_offset(n)?;
+        if len != self.atoms {
+            return Err(Error::Invalid {
+                offset,
+                kind: ErrorKind::AtomCount {
+                    expected: len,
+                    found: self.atoms,
+                },
+            });
+        }
+        if self.has_unit_cell {
+            offset = self.record(offset, Some(48))?.end + 4;
+        }
+        // x, y and z are separate records; a fourth dimension, if any, is ignored
+        for axis in 0..3 {
+            let record = self.record(offset, Some(self.atoms * 4))?;
+            for i in 0..self.atoms {
+                set(
+                    i,
+                    axis,
+                    f32::from_bits(self.i32_at(record.start + i * 4) as u32),
+                );
+            }
+            offset = record.end + 4;
+        }
+        Ok(())
+    }
+
+    /// The six unit cell values of frame `n` as written: `A, gamma, B, beta, alpha, C`.
+    /// Depending on the writer the angles are either degrees or cosines.
+    pub fn unit_cell(&self, n: usize) -> Result<Option<[f64; 6]>, Error> {
+        if !self.has_unit_cell {
+            return Ok(None);
+        }
+        let record = self.record(self.frame_offset(n)?, Some(48))?;
+        let mut cell = [0.0; 6];
+        for (i, v) in cell.iter_mut().enumerate() {
+            let bytes = self.map[record.start + i * 8..record.start + (i + 1) * 8]
+                .try_into()
+                .unwrap();
+            *v = if self.big_endian {
+                f64::from_be_bytes(bytes)
+            } else {
+                f64::from_le_bytes(bytes)
+            };
+        }
+        Ok(Some(cell))
+    }
+
+    /// Every frame has the same size, so the offset of any frame follows from the
+    /// header without scanning.
+    fn frame_offset(&self, n: usize) -> Result<usize, Error> {
+        if n >= self.frames {
+            return Err(Error::Invalid {
+                offset: self.map.len(),
+                kind: ErrorKind::NoSuchFrame(n),
+            });
+        }
+        Ok(self.first_frame + n * self.frame_len)
+    }
+
+    /// Check the Fortran record starting at `offset` and return the byte range of its
+    /// payload. Record markers are the payload length, before and after the payload.
+    fn record(
+        &self,
+        offset: usize,
+        expected: Option<usize>,
+    ) -> Result<std::ops::Range<usize>, Error> {
+        let marker_at = |at: usize| {
+            if at + 4 > self.map.len() {
+                Err(Error::Invalid {
+                    offset: at,
+                    kind: ErrorKind::Truncated,
+                })
+            } else {
+                Ok(self.i32_at(at) as u32)
+            }
+        };
+        let len = marker_at(offset)?;
+        if let Some(expected) = expected {
+            if len as usize != expected {
+                return Err(Error::Invalid {
+                    offset,
+                    kind: ErrorKind::RecordMarker {
+                        expected,
+                        found: len,
+                    },
+                });
+            }
+        }
+        let start = offset + 4;
+        let end = start + len as usize;
+        let trailer = marker_at(end)?;
+        if trailer != len {
+            return Err(Error::Invalid {
+                offset: end,
+                kind: ErrorKind::RecordMarker {
+                    expected: len as usize,
+                    found: trailer,
+                },
+            });
+        }
+        Ok(start..end)
+    }
+
+    /// Callers ensure `at + 4` is in bounds.
+    fn i32_at(&self, at: usize) -> i32 {
+        let bytes = self.map[at..at + 4].try_into().unwrap();
+        if self.big_endian {
+            i32::from_be_bytes(bytes)
+        } else {
+            i32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Invalid { offset, kind } => write!(f, "byte {offset}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::NotDcd => write!(f, "not a DCD file"),
+            ErrorKind::RecordMarker { expected, found } => {
+                write!(f, "expected a record of {expected} bytes, found {found}")
+            }
+            ErrorKind::FixedAtoms => write!(f, "trajectories with fixed atoms are not supported"),
+            ErrorKind::Truncated => write!(f, "file is truncated"),
+            ErrorKind::NoSuchFrame(n) => write!(f, "no frame {n}"),
+            ErrorKind::AtomCount { expected, found } => {
+                write!(f, "expected {expected} atoms, found {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use memmap2::MmapMut;
+
+    use super::*;
+
+    /// A CHARMM style DCD with a unit cell, 3 atoms and 2 frames, in either byte order.
+    fn write(big_endian: bool) -> Vec<u8> {
+        let int = |v: i32| {
+            if big_endian {
+                v.to_be_bytes()
+            } else {
+                v.to_le_bytes()
+            }
+        };
+        let mut out = vec![];
+        let mut record = |payload: &[u8]| {
+            out.extend(int(payload.len() as i32));
+            out.extend(payload);
+            out.extend(int(payload.len() as i32));
+        };
+
+        let mut control = [0; 20];
+        control[0] = 2;
+        control[1] = 100;
+        control[2] = 10;
+        control[9] = 0.5f32.to_bits() as i32;
+        control[10] = 1;
+        control[19] = 24;
+        let mut header = b"CORD".to_vec();
+        header.extend(control.iter().flat_map(|&c| int(c)));
+        record(&header);
+
+        let mut title = int(1).to_vec();
+        title.extend(format!("{:80}", "test").bytes());
+        record(&title);
+        record(&int(3));
+
+        for frame in 0..2 {
+            let cell: Vec<u8> = [10.0f64, 90.0, 20.0, 90.0, 90.0, 30.0]
+                .iter()
+                .flat_map(|v| {
+                    if big_endian {
+                        v.to_be_bytes()
+                    } else {
+                        v.to_le_bytes()
+                    }
+                })
+                .collect();
+            record(&cell);
+            for axis in 0..3 {
+                let values: Vec<u8> = (0..3)
+                    .flat_map(
+                        |atom| int(((frame * 100 + atom * 10 + axis) as f32).to_bits() as i32),
+                    )
+                    .collect();
+                record(&values);
+            }
+        }
+        out
+    }
+
+    fn open(bytes: &[u8]) -> Result<Dcd, Error> {
+        let mut map = MmapMut::map_anon(bytes.len()).unwrap();
+        map.copy_from_slice(bytes);
+        Dcd::from_map(map.make_read_only().unwrap())
+    }
+
+    #[test]
+    fn read_frames_in_either_byte_order() {
+        for big_endian in [false, true] {
+            let dcd = open(&write(big_endian)).unwrap();
+            assert_eq!((dcd.atom_count(), dcd.len()), (3, 2));
+            assert_eq!(dcd.title, "test");
+            assert_eq!((dcd.start_step, dcd.step_interval), (100, 10));
+            assert_eq!(dcd.timestep, 0.5);
+            assert_eq!(
+                dcd.positions(1).unwrap(),
+                [
+                    [100.0, 101.0, 102.0],
+                    [110.0, 111.0, 112.0],
+                    [120.0, 121.0, 122.0]
+                ]
+            );
+            assert_eq!(
+                dcd.unit_cell(0).unwrap(),
+                Some([10.0, 90.0, 20.0, 90.0, 90.0, 30.0])
+            );
+            assert!(dcd.positions(2).is_err());
+        }
+    }
+
+    #[test]
+    fn wrong_atom_count_is_an_error() {
+        let dcd = open(&write(false)).unwrap();
+        let mut positions = vec![[0.0; 3]; 2];
+        assert!(matches!(
+            dcd.read_positions(0, &mut positions),
+            Err(Error::Invalid {
+                kind: ErrorKind::AtomCount {
+                    expected: 2,
+                    found: 3
+                },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn partial_frame_is_ignored() {
+        let bytes = write(false);
+        let dcd = open(&bytes[..bytes.len() - 10]).unwrap();
+        assert_eq!(dcd.len(), 1);
+        assert_eq!(dcd.positions(0).unwrap()[2], [20.0, 21.0, 22.0]);
+    }
+}
@@ -0,0 +1,119 @@
+/// Per-element display properties.
+pub struct Element {
+    pub symbol: &'static str,
+    pub number: u8,
+    /// van der Waals radius in angstroms
+    pub radius: f32,
+    /// Jmol-style CPK color
+    pub color: [f32; 3],
+}
+
+/// Used for symbols not found in the table.
+pub const UNKNOWN: Element = Element {
+    symbol: "X",
+    number: 0,
+    radius: 1.5,
+    color: [1.0, 0.08, 0.58],
+};
+
+const fn el(symbol: &'static str, number: u8, radius: f32, color: [f32; 3]) -> Element {
+    Element {
+        symbol,
+        number,
+        radius,
+        color,
+    }
+}
+
+pub const ELEMENTS: &[Element] = &[
+    el("H", 1, 1.10, [1.0, 1.0, 1.0]),
+    el("He", 2, 1.40, [0.85, 1.0, 1.0]),
+    el("Li", 3, 1.82, [0.8, 0.5, 1.0]),
+    el("Be", 4, 1.53, [0.76, 1.0, 0.0]),
+    el("B", 5, 1.92, [1.0, 0.71, 0.71]),
+    el("C", 6, 1.70, [0.56, 0.56, 0.56]),
+    el("N", 7, 1.55, [0.19, 0.31, 0.97]),
+    el("O", 8, 1.52, [1.0, 0.05, 0.05]),
+    el("F", 9, 1.47, [0.56, 0.88, 0.31]),
+    el("Ne", 10, 1.54, [0.7, 0.89, 0.96]),
+    el("Na", 11, 2.27, [0.67, 0.36, 0.95]),
+    el("Mg", 12, 1.73, [0.54, 1.0, 0.0]),
+    el("Al", 13, 1.84, [0.75, 0.65, 0.65]),
+    el("Si", 14, 2.10, [0.94, 0.78, 0.63]),
+    el("P", 15, 1.80, [1.0, 0.5, 0.0]),
+    el("S", 16, 1.80, [1.0, 1.0, 0.19]),
+    el("Cl", 17, 1.75, [0.12, 0.94, 0.12]),
+    el("Ar", 18, 1.88, [0.5, 0.82, 0.89]),
+    el("K", 19, 2.75, [0.56, 0.25, 0.83]),
+    el("Ca", 20, 2.31, [0.24, 1.0, 0.0]),
+    el("Sc", 21, 2.11, [0.9, 0.9, 0.9]),
+    el("Ti", 22, 1.87, [0.75, 0.76, 0.78]),
+    el("V", 23, 1.79, [0.65, 0.65, 0.67]),
+    el("Cr", 24, 1.89, [0.54, 0.6, 0.78]),
+    el("Mn", 25, 1.97, [0.61, 0.48, 0.78]),
+    el("Fe", 26, 1.94, [0.88, 0.4, 0.2]),
+    el("Co", 27, 1.92, [0.94, 0.56, 0.63]),
+    el("Ni", 28, 1.63, [0.31, 0.82, 0.31]),
+    el("Cu", 29, 1.40, [0.78, 0.5, 0.2]),
+    el("Zn", 30, 1.39, [0.49, 0.5, 0.69]),
+    el("Ga", 31, 1.87, [0.76, 0.56, 0.56]),
+    el("Ge", 32, 2.11, [0.4, 0.56, 0.56]),
+    el("As", 33, 1.85, [0.74, 0.5, 0.89]),
+    el("Se", 34, 1.90, [1.0, 0.63, 0.0]),
+    el("Br", 35, 1.85, [0.65, 0.16, 0.16]),
+    el("Kr", 36, 2.02, [0.36, 0.72, 0.82]),
+    el("Rb", 37, 3.03, [0.44, 0.18, 0.69]),
+    el("Sr", 38, 2.49, [0.0, 1.0, 0.0]),
+    el("Y", 39, 2.19, [0.58, 1.0, 1.0]),
+    el("Zr", 40, 1.86, [0.58, 0.88, 0.88]),
+    el("Nb", 41, 2.07, [0.45, 0.76, 0.79]),
+    el("Mo", 42, 2.09, [0.33, 0.71, 0.71]),
+    el("Tc", 43, 2.09, [0.23, 0.62, 0.62]),
+    el("Ru", 44, 2.07, [0.14, 0.56, 0.56]),
+    el("Rh", 45, 1.95, [0.04, 0.49, 0.55]),
+    el("Pd", 46, 2.02, [0.0, 0.41, 0.52]),
+    el("Ag", 47, 1.72, [0.75, 0.75, 0.75]),
+    el("Cd", 48, 1.58, [1.0, 0.85, 0.56]),
+    el("In", 49, 1.93, [0.65, 0.46, 0.45]),
+    el("Sn", 50, 2.17, [0.4, 0.5, 0.5]),
+    el("Sb", 51, 2.06, [0.62, 0.39, 0.71]),
+    el("Te", 52, 2.06, [0.83, 0.48, 0.0]),
+    el("I", 53, 1.98, [0.58, 0.0, 0.58]),
+    el("Xe", 54, 2.16, [0.26, 0.62, 0.69]),
+    el("Cs", 55, 3.43, [0.34, 0.09, 0.56]),
+    el("Ba", 56, 2.68, [0.0, 0.79, 0.0]),
+    el("La", 57, 2.43, [0.44, 0.83, 1.0]),
+    el("Ce", 58, 2.42, [1.0, 1.0, 0.78]),
+    el("Gd", 64, 2.34, [0.27, 1.0, 0.78]),
+    el("Yb", 70, 2.26, [0.0, 0.75, 0.22]),
+    el("Hf", 72, 2.12, [0.3, 0.76, 1.0]),
+    el("Ta", 73, 2.17, [0.3, 0.65, 1.0]),
+    el("W", 74, 2.10, [0.13, 0.58, 0.84]),
+    el("Re", 75, 2.17, [0.15, 0.49, 0.67]),
+    el("Os", 76, 2.16, [0.15, 0.4, 0.59]),
+    el("Ir", 77, 2.02, [0.09, 0.33, 0.53]),
+    el("Pt", 78, 1.75, [0.82, 0.82, 0.88]),
+    el("Au", 79, 1.66, [1.0, 0.82, 0.14]),
+    el("Hg", 80, 1.55, [0.72, 0.72, 0.82]),
+    el("Tl", 81, 1.96, [0.65, 0.33, 0.3]),
+    el("Pb", 82, 2.02, [0.34, 0.35, 0.38]),
+    el("Bi", 83, 2.07, [0.62, 0.31, 0.71]),
+    el("U", 92, 1.86, [0.0, 0.56, 1.0]),
+];
+
+/// Look up an element by symbol, ignoring case and surrounding whitespace.
+pub fn lookup(symbol: &str) -> Option<&'static Element> {
+    let symbol = symbol.trim();
+    ELEMENTS
+        .iter()
+        .find(|e| e.symbol.eq_ignore_ascii_case(symbol))
+}
+
+pub fn by_number(number: u8) -> Option<&'static Element> {
+    ELEMENTS.iter().find(|e| e.number == number)
+}
+
+/// Like [`lookup`] but falls back to [`UNKNOWN`].
+pub fn lookup_or_unknown(symbol: &str) -> &'static Element {
+    lookup(symbol).unwrap_or(&UNKNOWN)
+}
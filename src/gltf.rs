@@ -0,0 +1,293 @@
+//! Export of atoms to binary glTF 2.0 (`.glb`) files, for Blender and other 3D tools.
+//!
+//! Every atom is a sphere, and atoms of the same color share a sphere mesh and a
+//! material. With [`Instancing::Gpu`] each color is one node carrying a translation and
+//! scale per atom through `EXT_mesh_gpu_instancing`. Tools without that extension can
+//! be given [`Instancing::Baked`], where the spheres are copied into one mesh per color.
+//!
+//! The camera is exported as well, so the file opens looking the way the viewer did.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use glam::Vec3;
+
+use crate::{camera::Camera, render_pipeline::AtomCpu, sphere, volume::Mesh};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub instancing: Instancing,
+    /// Detail of the sphere mesh, see [`sphere::icosphere`].
+    pub subdivisions: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instancing {
+    /// One sphere mesh per color, placed once per atom by `EXT_mesh_gpu_instancing`.
+    Gpu,
+    /// Every sphere written out in full.
+    Baked,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            instancing: Instancing::Gpu,
+            subdivisions: 2,
+        }
+    }
+}
+
+pub fn export(
+    path: impl AsRef<Path>,
+    atoms: &[AtomCpu],
+    camera: &Camera,
+    options: &Options,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write(&mut file, atoms, camera, options)?;
+    file.flush()
+}
+
+/// Write `atoms` as seen through `camera`.
+pub fn write(
+    mut w: impl Write,
+    atoms: &[AtomCpu],
+    camera: &Camera,
+    options: &Options,
+) -> io::Result<()> {
+    let mut gltf = Gltf::default();
+    let sphere = sphere::icosphere(options.subdivisions);
+
+    // group by color, in order of first appearance
+    let mut groups: Vec<([f32; 3], Vec<AtomCpu>)> = vec![];
+    let mut group_of: HashMap<[u32; 3], usize> = HashMap::new();
+    for atom in atoms {
+        let group = *group_of
+            .entry(atom.color.map(f32::to_bits))
+            .or_insert_with(|| {
+                groups.push((atom.color, vec![]));
+                groups.len() - 1
+            });
+        groups[group].1.push(*atom);
+    }
+
+    let shared_mesh = match options.instancing {
+        Instancing::Gpu => Some(gltf.mesh_attributes(&sphere)),
+        Instancing::Baked => None,
+    };
+    for (color, atoms) in &groups {
+        let material = gltf.materials.len();
+        gltf.materials.push(format!(
+            r#"{{"pbrMetallicRoughness":{{"baseColorFactor":[{},{},{},1],"metallicFactor":0,"roughnessFactor":0.5}}}}"#,
+            color[0], color[1], color[2]
+        ));
+
+        let node = match shared_mesh {
+            Some(attributes) => {
+                let mesh = gltf.mesh(attributes, material);
+                let translations: Vec<[f32; 3]> = atoms.iter().map(|a| a.pos).collect();
+                let scales: Vec<[f32; 3]> = atoms.iter().map(|a| [a.radius; 3]).collect();
+                let translation = gltf.vec3_accessor(&translations, None, false);
+                let scale = gltf.vec3_accessor(&scales, None, false);
+                format!(
+                    r#"{{"mesh":{mesh},"extensions":{{"EXT_mesh_gpu_instancing":{{"attributes":{{"TRANSLATION":{translation},"SCALE":{scale}}}}}}}}}"#
+                )
+            }
+            None => {
+                let attributes = gltf.mesh_attributes(&sphere::bake(&sphere, atoms));
+                format!(r#"{{"mesh":{}}}"#, gltf.mesh(attributes, material))
+            }
+        };
+        gltf.nodes.push(node);
+    }
+
+    // glTF cameras look down -z as the view does, so the node is placed by the inverse
+    gltf.nodes.push(format!(
+        r#"{{"camera":0,"matrix":{}}}"#,
+        json_array(&camera.view.inverse().to_cols_array())
+    ));
+
+    gltf.write(&mut w, camera, options.instancing == Instancing::Gpu)
+}
+
+/// The JSON and binary chunks as they are built up. JSON objects are kept as strings.
+#[derive(Default)]
+struct Gltf {
+    bin: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    meshes: Vec<String>,
+    materials: Vec<String>,
+    nodes: Vec<String>,
+}
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// Accessors for the position, normal and index data of a mesh.
+#[derive(Clone, Copy)]
+struct Attributes {
+    positions: usize,
+    normals: usize,
+    indices: usize,
+}
+
+impl Gltf {
+    fn mesh_attributes(&mut self, mesh: &Mesh) -> Attributes {
+        Attributes {
+            // glTF requires bounds on positions
+            positions: self.vec3_accessor(&mesh.positions, Some(ARRAY_BUFFER), true),
+            normals: self.vec3_accessor(&mesh.normals, Some(ARRAY_BUFFER), false),
+            indices: self.accessor(
+                bytemuck::cast_slice(&mesh.indices),
+                mesh.indices.len(),
+                UNSIGNED_INT,
+                "SCALAR",
+                Some(ELEMENT_ARRAY_BUFFER),
+                "",
+            ),
+        }
+    }
+
+    fn mesh(&mut self, attributes: Attributes, material: usize) -> usize {
+        let Attributes {
+            positions,
+            normals,
+            indices,
+        } = attributes;
+        self.meshes.push(format!(
+            r#"{{"primitives":[{{"attributes":{{"POSITION":{positions},"NORMAL":{normals}}},"indices":{indices},"material":{material}}}]}}"#
+        ));
+        self.meshes.len() - 1
+    }
+
+    fn vec3_accessor(&mut self, data: &[[f32; 3]], target: Option<u32>, bounds: bool) -> usize {
+        let mut extra = String::new();
+        if bounds {
+            let (min, max) = data.iter().fold(
+                (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+                |(min, max), &p| (min.min(p.into()), max.max(p.into())),
+            );
+            write!(
+                extra,
+                r#","min":{},"max":{}"#,
+                json_array(&min.to_array()),
+                json_array(&max.to_array())
+            )
+            .unwrap();
+        }
+        self.accessor(
+            bytemuck::cast_slice(data),
+            data.len(),
+            FLOAT,
+            "VEC3",
+            target,
+            &extra,
+        )
+    }
+
+    /// Append `data` to the binary chunk with a buffer view and accessor of its own,
+    /// returning the accessor index. `extra` is spliced into the accessor object.
+    fn accessor(
+        &mut self,
+        data: &[u8],
+        count: usize,
+        component_type: u32,
+        type_: &str,
+        target: Option<u32>,
+        extra: &str,
+    ) -> usize {
+        // every component is four bytes, so views stay aligned
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(data);
+        let target = target.map_or(String::new(), |t| format!(r#","target":{t}"#));
+        self.buffer_views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{}{target}}}"#,
+            data.len()
+        ));
+        self.accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{component_type},"count":{count},"type":"{type_}"{extra}}}"#,
+            self.buffer_views.len() - 1
+        ));
+        self.accessors.len() - 1
+    }
+
+    fn write(&self, w: &mut impl Write, camera: &Camera, gpu_instancing: bool) -> io::Result<()> {
+        let mut json = String::from(r#"{"asset":{"version":"2.0","generator":"bddatoms"}"#);
+        if gpu_instancing {
+            json += r#","extensionsUsed":["EXT_mesh_gpu_instancing"]"#;
+        }
+        let nodes: Vec<String> = (0..self.nodes.len()).map(|n| n.to_string()).collect();
+        write!(
+            json,
+            r#","scene":0,"scenes":[{{"nodes":[{}]}}]"#,
+            nodes.join(",")
+        )
+        .unwrap();
+        let Camera {
+            fov_y,
+            aspect,
+            near,
+            far,
+            ..
+        } = camera;
+        let camera = format!(
+            r#"{{"type":"perspective","perspective":{{"aspectRatio":{aspect},"yfov":{fov_y},"znear":{near},"zfar":{far}}}}}"#
+        );
+        let buffer = format!(r#"{{"byteLength":{}}}"#, self.bin.len());
+        let buffers = if self.bin.is_empty() {
+            vec![]
+        } else {
+            vec![buffer]
+        };
+        // glTF does not allow empty arrays, so leave out what an empty scene lacks
+        for (name, items) in [
+            ("nodes", &self.nodes),
+            ("meshes", &self.meshes),
+            ("materials", &self.materials),
+            ("accessors", &self.accessors),
+            ("bufferViews", &self.buffer_views),
+            ("buffers", &buffers),
+            ("cameras", &vec![camera]),
+        ] {
+            if !items.is_empty() {
+                write!(json, r#","{name}":[{}]"#, items.join(",")).unwrap();
+            }
+        }
+        json.push('}');
+        let mut json = json.into_bytes();
+
+        // chunks are padded to four bytes, JSON with spaces and binary with zeros
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = self.bin.clone();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let bin_chunk_len = if bin.is_empty() { 0 } else { 8 + bin.len() };
+        let total = 12 + 8 + json.len() + bin_chunk_len;
+        w.write_all(b"glTF")?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&(total as u32).to_le_bytes())?;
+        w.write_all(&(json.len() as u32).to_le_bytes())?;
+        w.write_all(b"JSON")?;
+        w.write_all(&json)?;
+        if !bin.is_empty() {
+            w.write_all(&(bin.len() as u32).to_le_bytes())?;
+            w.write_all(b"BIN\0")?;
+            w.write_all(&bin)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_array(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(f32::to_string).collect();
+    format!("[{}]", values.join(","))
+}
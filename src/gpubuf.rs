@@ -70,6 +70,11 @@ impl<T> GpuBuf<T> {
         self.length
     }
 
+    /// number of elements that fit without reallocating
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     // returns None if the slice would be empty
     // this is because wgpu does not allow empty slices
     pub fn slice(&self) -> Option<wgpu::BufferSlice> {
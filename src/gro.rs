@@ -0,0 +1,290 @@
+//! Reader for GROMACS `.gro` files. Several frames may be concatenated in one file.
+//!
+//! GROMACS works in nanometers, everything here is converted to angstroms to match the
+//! other readers.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+const ANGSTROMS_PER_NM: f32 = 10.0;
+
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub title: String,
+    /// picoseconds, from a `t=` in the title
+    pub time: Option<f32>,
+    pub atoms: Vec<Atom>,
+    /// Box vectors in angstroms, one per row. Rectangular boxes only have a diagonal.
+    pub box_vectors: [[f32; 3]; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub res_seq: i32,
+    pub res_name: String,
+    pub name: String,
+    pub serial: u32,
+    /// angstroms
+    pub pos: [f32; 3],
+    /// angstroms per picosecond
+    pub velocity: Option<[f32; 3]>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidAtomCount(String),
+    /// The file ended in the middle of a frame.
+    Truncated,
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    InvalidBox(String),
+}
+
+impl Frame {
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms.iter().map(Atom::to_atom_cpu).collect()
+    }
+}
+
+impl Atom {
+    /// .gro files do not record elements, so guess from the atom name. Two letter
+    /// elements are only considered for single atom residues such as `NA` or `CL` ions,
+    /// since `CA` is far more likely to be an alpha carbon than calcium.
+    pub fn element(&self) -> &'static element::Element {
+        let name = self.name.trim_start_matches(|c: char| c.is_ascii_digit());
+        if name.eq_ignore_ascii_case(&self.res_name) {
+            if let Some(e) = element::lookup(name) {
+                return e;
+            }
+        }
+        element::lookup_or_unknown(name.get(..1).unwrap_or_default())
+    }
+
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = self.element();
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+/// Streams frames out of a reader one at a time.
+pub struct Reader<R> {
+    inner: R,
+    line: usize,
+    buf: String,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(
+            File::open(path).map_err(Error::Io)?,
+        )))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Returns false at end of input.
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.buf.clear();
+        let read = self.inner.read_line(&mut self.buf).map_err(Error::Io)?;
+        self.line += 1;
+        let trimmed = self.buf.trim_end_matches(['\n', '\r']).len();
+        self.buf.truncate(trimmed);
+        Ok(read != 0)
+    }
+
+    fn require_line(&mut self) -> Result<&str, Error> {
+        if self.next_line()? {
+            Ok(&self.buf)
+        } else {
+            Err(self.error(ErrorKind::Truncated))
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        if !self.next_line()? {
+            return Ok(None);
+        }
+        let title = self.buf.clone();
+        // tolerate trailing blank lines
+        if !self.next_line()? && title.trim().is_empty() {
+            return Ok(None);
+        }
+        let count: usize = self
+            .buf
+            .trim()
+            .parse()
+            .map_err(|_| self.error(ErrorKind::InvalidAtomCount(self.buf.clone())))?;
+
+        let mut frame = Frame {
+            time: parse_time(&title),
+            title,
+            atoms: Vec::with_capacity(count),
+            box_vectors: Default::default(),
+        };
+
+        // coordinate precision is variable, the field width is the distance between
+        // decimal points
+        let mut width = None;
+        for _ in 0..count {
+            let line = self.require_line()?;
+            let width = *width.get_or_insert_with(|| field_width(line));
+            let atom = parse_atom(line, width).map_err(|k| self.error(k))?;
+            frame.atoms.push(atom);
+        }
+
+        let line = self.require_line()?;
+        frame.box_vectors =
+            parse_box(line).ok_or_else(|| self.error(ErrorKind::InvalidBox(self.buf.clone())))?;
+
+        Ok(Some(frame))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+pub fn parse(src: &str) -> Result<Vec<Frame>, Error> {
+    Reader::new(src.as_bytes()).collect()
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Frame>, Error> {
+    Reader::open(path)?.collect()
+}
+
+/// GROMACS writes titles like `Protein in water t= 100.00000 step= 50000`.
+fn parse_time(title: &str) -> Option<f32> {
+    let after = &title[title.find("t=")? + 2..];
+    after.split_whitespace().next()?.parse().ok()
+}
+
+fn field_width(line: &str) -> usize {
+    let coords = line.get(20..).unwrap_or_default();
+    match coords.find('.') {
+        Some(first) => coords[first + 1..].find('.').map_or(8, |second| second + 1),
+        None => 8,
+    }
+}
+
+/// `%5d%-5s%5s%5d` followed by three coordinates and optionally three velocities.
+fn parse_atom(line: &str, width: usize) -> Result<Atom, ErrorKind> {
+    let field = |range: Range<usize>| {
+        line.get(range.start..range.end.min(line.len()))
+            .unwrap_or_default()
+            .trim()
+    };
+    let num = |range: Range<usize>, name: &'static str| {
+        let s = field(range);
+        s.parse::<f32>().map_err(|_| ErrorKind::InvalidField {
+            field: name,
+            value: s.to_string(),
+        })
+    };
+    let column = |i: usize| 20 + i * width..20 + (i + 1) * width;
+
+    let res_seq = field(0..5);
+    let serial = field(15..20);
+    let pos = [
+        num(column(0), "x")? * ANGSTROMS_PER_NM,
+        num(column(1), "y")? * ANGSTROMS_PER_NM,
+        num(column(2), "z")? * ANGSTROMS_PER_NM,
+    ];
+    let velocity = if field(column(3)).is_empty() {
+        None
+    } else {
+        Some([
+            num(column(3), "vx")? * ANGSTROMS_PER_NM,
+            num(column(4), "vy")? * ANGSTROMS_PER_NM,
+            num(column(5), "vz")? * ANGSTROMS_PER_NM,
+        ])
+    };
+
+    Ok(Atom {
+        res_seq: res_seq.parse().map_err(|_| ErrorKind::InvalidField {
+            field: "residue number",
+            value: res_seq.to_string(),
+        })?,
+        res_name: field(5..10).to_string(),
+        name: field(10..15).to_string(),
+        // atom numbers wrap around after 99999, so they are informational only
+        serial: serial.parse().unwrap_or(0),
+        pos,
+        velocity,
+    })
+}
+
+/// `v1(x) v2(y) v3(z) [v1(y) v1(z) v2(x) v2(z) v3(x) v3(y)]`
+fn parse_box(line: &str) -> Option<[[f32; 3]; 3]> {
+    let v = line
+        .split_whitespace()
+        .map(|s| s.parse::<f32>().map(|v| v * ANGSTROMS_PER_NM))
+        .collect::<Result<Vec<f32>, _>>()
+        .ok()?;
+    match v.len() {
+        3 => Some([[v[0], 0.0, 0.0], [0.0, v[1], 0.0], [0.0, 0.0, v[2]]]),
+        9 => Some([[v[0], v[3], v[4]], [v[5], v[1], v[6]], [v[7], v[8], v[2]]]),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidAtomCount(s) => write!(f, "invalid atom count: {s:?}"),
+            ErrorKind::Truncated => write!(f, "file ends in the middle of a frame"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::InvalidBox(s) => write!(f, "invalid box vectors: {s:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
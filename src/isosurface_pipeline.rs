@@ -0,0 +1,53 @@
+use shame::prelude::*;
+
+use crate::render_pipeline::{light_direction, lighting, CameraGpu};
+
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct SurfaceVertexCpu {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 3],
+}
+
+#[derive(shame::Fields)]
+struct SurfaceVertexGpu {
+    pos: float3,
+    normal: float3,
+    color: float3,
+}
+
+type UniformGpu = float4x4;
+
+pub fn features_used() -> wgpu::Features {
+    wgpu::Features::DEPTH_CLIP_CONTROL
+}
+
+pub fn pipeline(mut f: RenderFeatures) {
+    let index: TriangleList<u32> = f.io.index_buffer();
+
+    let vertex: SurfaceVertexGpu = f.io.vertex_buffer();
+    let camera: CameraGpu = f.io.group().uniform_block();
+    let tile: UniformGpu = f.io.group().uniform_block();
+
+    let projected = camera.projection * (camera.view * (vertex.pos, 1.0));
+    let clip_position = tile * projected;
+    let poly = f.raster.rasterize(clip_position, Cull::Off, index);
+
+    let normal = camera.view * (vertex.normal, 0.0);
+    let normal = poly.lerp(normal.xyz()).normalize();
+
+    // Both sides of the surface can be seen, so light falls on whichever side faces the
+    // light.
+    let facing = light_direction(camera.view).dot(normal);
+    let light = lighting(facing.max(0.0) + (0.0 - facing).max(0.0));
+
+    // same depth convention as the atom impostors, so surfaces and atoms occlude
+    // each other correctly
+    let depth = poly.lerp(projected.z()) / poly.lerp(projected.w());
+    f.io.depth::<Depth32>()
+        .test_write(DepthTest::Greater, DepthWrite::Write(depth));
+
+    let color = poly.lerp(vertex.color) * light;
+    f.io.color::<RGBA_Surface>().set((color, 1.0));
+}
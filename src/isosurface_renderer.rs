@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use glam::Mat4;
+use wgpu::IndexFormat;
+
+use crate::{
+    camera::Camera,
+    glue,
+    gpubuf::GpuBuf,
+    isosurface_pipeline::{self, SurfaceVertexCpu},
+    render_pipeline::{CameraCpu, UniformCpu},
+    volume::{Grid, Mesh},
+};
+
+/// Draws isosurfaces of a volumetric grid. Meant to be drawn in the same render pass as
+/// the atoms, against the same depth buffer.
+pub struct IsosurfaceRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    index_buf: GpuBuf<u32>,
+    vertex_buf: GpuBuf<SurfaceVertexCpu>,
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buf: GpuBuf<CameraCpu>,
+    tile_bind_group: wgpu::BindGroup,
+    tile_buf: GpuBuf<UniformCpu>,
+    positive_color: [f32; 3],
+    negative_color: [f32; 3],
+}
+
+impl IsosurfaceRenderer {
+    pub fn create(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        swapchain_format: wgpu::TextureFormat,
+    ) -> Self {
+        let recording = shame::record_render_pipeline(isosurface_pipeline::pipeline);
+        let (render_pipeline, bind_group_layouts) =
+            glue::make_render_pipeline(&recording, &device, Some(swapchain_format));
+
+        let vertex_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[],
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[],
+            wgpu::BufferUsages::INDEX,
+        );
+
+        let uniform_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Camera::default().uniform()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        assert_eq!(bind_group_layouts.len(), 2);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[0],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        let tile_buf = GpuBuf::initialize(
+            Arc::clone(&device),
+            Arc::clone(&queue),
+            &[Mat4::IDENTITY],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let tile_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layouts[1],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(tile_buf.as_entire_buffer_binding()),
+            }],
+            label: None,
+        });
+
+        Self {
+            vertex_buf,
+            index_buf,
+            render_pipeline,
+            bind_group,
+            uniform_buf,
+            tile_bind_group,
+            tile_buf,
+            positive_color: [0.1, 0.3, 0.9],
+            negative_color: [0.9, 0.2, 0.1],
+            queue,
+            device,
+        }
+    }
+
+    /// Colors for surfaces at positive and negative levels. Takes effect at the next
+    /// [`IsosurfaceRenderer::set_grid`].
+    pub fn set_colors(&mut self, positive: [f32; 3], negative: [f32; 3]) {
+        self.positive_color = positive;
+        self.negative_color = negative;
+    }
+
+    pub fn colors(&self) -> ([f32; 3], [f32; 3]) {
+        (self.positive_color, self.negative_color)
+    }
+
+    /// Replace the displayed surfaces with those of `grid` at each of `levels`. Pass
+    /// both `level` and `-level` to see both lobes of an orbital.
+    pub fn set_grid(&mut self, grid: &Grid, levels: &[f32]) {
+        let meshes: Vec<(Mesh, [f32; 3])> = levels
+            .iter()
+            .map(|&level| {
+                let color = if level < 0.0 {
+                    self.negative_color
+                } else {
+                    self.positive_color
+                };
+                (grid.isosurface(level), color)
+            })
+            .collect();
+        self.set_meshes(&meshes);
+    }
+
+    /// Replace the displayed surfaces, each drawn in a single color.
+    pub fn set_meshes(&mut self, meshes: &[(Mesh, [f32; 3])]) {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for (mesh, color) in meshes {
+            let base = vertices.len() as u32;
+            indices.extend(mesh.indices.iter().map(|i| base + i));
+            vertices.extend(
+                mesh.positions
+                    .iter()
+                    .zip(&mesh.normals)
+                    .map(|(&pos, &normal)| SurfaceVertexCpu {
+                        pos,
+                        normal,
+                        color: *color,
+                    }),
+            );
+        }
+
+        self.vertex_buf = GpuBuf::initialize(
+            Arc::clone(&self.device),
+            Arc::clone(&self.queue),
+            &vertices,
+            wgpu::BufferUsages::VERTEX,
+        );
+        self.index_buf = GpuBuf::initialize(
+            Arc::clone(&self.device),
+            Arc::clone(&self.queue),
+            &indices,
+            wgpu::BufferUsages::INDEX,
+        );
+    }
+
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.uniform_buf.copy_from_slice(&[camera.uniform()]);
+    }
+
+    /// Applied in clip space after everything else, to draw one tile of a larger image.
+    pub fn set_tile(&mut self, tile: Mat4) {
+        self.tile_buf.copy_from_slice(&[tile]);
+    }
+
+    /// write render commands to the command buffer
+    pub fn render<'a: 'b, 'b>(&'a self, pass: &mut wgpu::RenderPass<'b>) {
+        let (Some(vertex_slice), Some(index_slice)) =
+            (self.vertex_buf.slice(), self.index_buf.slice())
+        else {
+            return;
+        };
+
+        pass.set_pipeline(&self.render_pipeline);
+
+        pass.set_index_buffer(index_slice, IndexFormat::Uint32);
+        pass.set_vertex_buffer(0, vertex_slice);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, &self.tile_bind_group, &[]);
+
+        pass.draw_indexed(0..(self.index_buf.len() as u32), 0, 0..1);
+    }
+
+    pub fn need_features() -> wgpu::Features {
+        isosurface_pipeline::features_used()
+    }
+}
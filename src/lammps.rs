@@ -0,0 +1,617 @@
+//! Streaming reader for LAMMPS `dump custom` (and `dump atom`) trajectories.
+//!
+//! Columns are user defined, so [`Options`] decides how atom types become radii and
+//! colors and which extra per-atom columns are kept. Lengths are in whatever LAMMPS
+//! `units` style the simulation used, which is angstroms for `real` and `metal`.
+//!
+//! LAMMPS writes atoms in whatever order the processors hold them, so frames with an
+//! `id` column are sorted by id, keeping each atom at the same index from frame to frame.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Which kind of coordinate columns to use when a dump has several. When `None`, or
+    /// when the preferred columns are missing, the first available of wrapped,
+    /// unwrapped, scaled and scaled unwrapped is used.
+    pub coordinates: Option<Coordinates>,
+    /// Appearance per LAMMPS atom type.
+    pub types: HashMap<u32, Style>,
+    /// Used for atoms missing from `types` when the dump has no `element` column. Only
+    /// the radius is used for atoms with a type, which get a color of their own per type
+    /// so that types can be told apart.
+    pub default_style: Style,
+    /// Scalar columns to keep, such as `q`, `vx` or `c_pe`.
+    pub properties: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub radius: f32,
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Coordinates {
+    /// `x y z`
+    Wrapped,
+    /// `xu yu zu`
+    Unwrapped,
+    /// `xs ys zs`, fractions of the box
+    Scaled,
+    /// `xsu ysu zsu`
+    ScaledUnwrapped,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub timestep: u64,
+    pub cell: Cell,
+    pub atoms: Vec<Atom>,
+    /// The columns requested in `Options::properties` that were present, one value per
+    /// atom.
+    pub properties: Vec<(String, Vec<f32>)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub id: Option<u64>,
+    pub type_: Option<u32>,
+    pub element: Option<String>,
+    /// Cartesian, even when the dump had scaled coordinates.
+    pub pos: [f32; 3],
+}
+
+/// The simulation box, possibly triclinic.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cell {
+    pub origin: [f32; 3],
+    /// Edge vectors `a`, `b` and `c`, one per row. `a` lies along x and `b` in the xy
+    /// plane, as LAMMPS requires.
+    pub vectors: [[f32; 3]; 3],
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    UnexpectedLine(String),
+    /// The file ended in the middle of a frame.
+    Truncated,
+    InvalidField {
+        field: String,
+        value: String,
+    },
+    NoCoordinates,
+    InvalidBox(String),
+    /// Every frame read through [`Dump::read_atoms`] must have the same number of atoms.
+    AtomCount {
+        expected: usize,
+        found: usize,
+    },
+    NoSuchFrame(usize),
+}
+
+/// Colors for atom types missing from [`Options::types`], by type number.
+const TYPE_COLORS: [[f32; 3]; 8] = [
+    [0.56, 0.56, 0.56],
+    [1.0, 0.05, 0.05],
+    [0.19, 0.31, 0.97],
+    [1.0, 0.78, 0.16],
+    [0.2, 0.8, 0.2],
+    [0.8, 0.5, 1.0],
+    [1.0, 0.5, 0.0],
+    [0.25, 0.88, 0.82],
+];
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            coordinates: None,
+            types: HashMap::new(),
+            default_style: Style {
+                radius: 1.0,
+                color: [0.7, 0.7, 0.7],
+            },
+            properties: vec![],
+        }
+    }
+}
+
+impl Options {
+    pub fn style(&self, atom: &Atom) -> Style {
+        if let Some(style) = atom.type_.and_then(|t| self.types.get(&t)) {
+            return *style;
+        }
+        let element = atom.element.as_deref().and_then(element::lookup);
+        match (element, atom.type_) {
+            (Some(e), _) => Style {
+                radius: e.radius,
+                color: e.color,
+            },
+            // types count from 1
+            (None, Some(t)) => Style {
+                radius: self.default_style.radius,
+                color: TYPE_COLORS[t.saturating_sub(1) as usize % TYPE_COLORS.len()],
+            },
+            (None, None) => self.default_style,
+        }
+    }
+}
+
+impl Frame {
+    pub fn to_atoms(&self, options: &Options) -> Vec<AtomCpu> {
+        self.atoms
+            .iter()
+            .map(|a| {
+                let style = options.style(a);
+                AtomCpu {
+                    pos: a.pos,
+                    color: style.color,
+                    radius: style.radius,
+                }
+            })
+            .collect()
+    }
+
+    pub fn property(&self, name: &str) -> Option<&[f32]> {
+        self.properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    fn sort_by_id(&mut self) {
+        if self.atoms.windows(2).all(|w| w[0].id <= w[1].id)
+            || self.atoms.iter().any(|a| a.id.is_none())
+        {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.atoms.len()).collect();
+        order.sort_by_key(|&i| self.atoms[i].id);
+        self.atoms = order.iter().map(|&i| self.atoms[i].clone()).collect();
+        for (_, values) in &mut self.properties {
+            *values = order.iter().map(|&i| values[i]).collect();
+        }
+    }
+}
+
+impl Cell {
+    pub fn fractional_to_cartesian(&self, s: [f32; 3]) -> [f32; 3] {
+        let [a, b, c] = self.vectors;
+        let mut out = self.origin;
+        for i in 0..3 {
+            out[i] += s[0] * a[i] + s[1] * b[i] + s[2] * c[i];
+        }
+        out
+    }
+
+    /// From the three `ITEM: BOX BOUNDS` lines. Triclinic dumps list the bounding box
+    /// of the cell together with the tilt factors `xy xz yz`.
+    fn from_bounds(bounds: [[f32; 3]; 3], triclinic: bool) -> Self {
+        let [[xlo, xhi, xy], [ylo, yhi, xz], [zlo, zhi, yz]] = bounds;
+        let (xy, xz, yz) = if triclinic {
+            (xy, xz, yz)
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let xlo = xlo - 0f32.min(xy).min(xz).min(xy + xz);
+        let xhi = xhi - 0f32.max(xy).max(xz).max(xy + xz);
+        let ylo = ylo - 0f32.min(yz);
+        let yhi = yhi - 0f32.max(yz);
+        Cell {
+            origin: [xlo, ylo, zlo],
+            vectors: [
+                [xhi - xlo, 0.0, 0.0],
+                [xy, yhi - ylo, 0.0],
+                [xz, yz, zhi - zlo],
+            ],
+        }
+    }
+}
+
+/// Column indices of the `ITEM: ATOMS` line, worked out once per frame.
+struct Layout {
+    id: Option<usize>,
+    type_: Option<usize>,
+    element: Option<usize>,
+    pos: [usize; 3],
+    scaled: bool,
+    properties: Vec<(String, usize)>,
+    len: usize,
+}
+
+impl Layout {
+    fn new(columns: &[&str], options: &Options) -> Result<Self, ErrorKind> {
+        let find = |name: &str| columns.iter().position(|c| *c == name);
+        let kinds = [
+            Coordinates::Wrapped,
+            Coordinates::Unwrapped,
+            Coordinates::Scaled,
+            Coordinates::ScaledUnwrapped,
+        ];
+        let names = |kind| match kind {
+            Coordinates::Wrapped => ["x", "y", "z"],
+            Coordinates::Unwrapped => ["xu", "yu", "zu"],
+            Coordinates::Scaled => ["xs", "ys", "zs"],
+            Coordinates::ScaledUnwrapped => ["xsu", "ysu", "zsu"],
+        };
+        let (kind, pos) = options
+            .coordinates
+            .into_iter()
+            .chain(kinds)
+            .find_map(|kind| {
+                let [x, y, z] = names(kind);
+                Some((kind, [find(x)?, find(y)?, find(z)?]))
+            })
+            .ok_or(ErrorKind::NoCoordinates)?;
+
+        Ok(Self {
+            id: find("id"),
+            type_: find("type"),
+            element: find("element"),
+            pos,
+            scaled: matches!(kind, Coordinates::Scaled | Coordinates::ScaledUnwrapped),
+            properties: options
+                .properties
+                .iter()
+                .filter_map(|p| Some((p.clone(), find(p)?)))
+                .collect(),
+            len: columns.len(),
+        })
+    }
+}
+
+/// Streams frames out of a dump file one at a time.
+pub struct Reader<R> {
+    inner: R,
+    options: Options,
+    line: usize,
+    buf: String,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::Io)?;
+        Ok(Self::new(BufReader::new(file), options))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R, options: Options) -> Self {
+        Self {
+            inner,
+            options,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Returns false at end of input.
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.buf.clear();
+        let read = self.inner.read_line(&mut self.buf).map_err(Error::Io)?;
+        self.line += 1;
+        let trimmed = self.buf.trim_end_matches(['\n', '\r']).len();
+        self.buf.truncate(trimmed);
+        Ok(read != 0)
+    }
+
+    fn require_line(&mut self) -> Result<&str, Error> {
+        if self.next_line()? {
+            Ok(&self.buf)
+        } else {
+            Err(self.error(ErrorKind::Truncated))
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        self.error(ErrorKind::UnexpectedLine(self.buf.clone()))
+    }
+
+    fn value<T: std::str::FromStr>(&mut self, field: &str) -> Result<T, Error> {
+        self.require_line()?;
+        let line = self.buf.trim();
+        line.parse().map_err(|_| {
+            let kind = ErrorKind::InvalidField {
+                field: field.to_string(),
+                value: line.to_string(),
+            };
+            self.error(kind)
+        })
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            if !self.buf.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut frame = Frame::default();
+        let mut count = None;
+        loop {
+            let item = match self.buf.strip_prefix("ITEM:") {
+                Some(item) => item.trim().to_string(),
+                None => return Err(self.unexpected()),
+            };
+            if item == "TIMESTEP" {
+                frame.timestep = self.value("TIMESTEP")?;
+            } else if item == "NUMBER OF ATOMS" {
+                count = Some(self.value::<usize>("NUMBER OF ATOMS")?);
+            } else if let Some(flags) = item.strip_prefix("BOX BOUNDS") {
+                let triclinic = flags.contains("xy");
+                let mut bounds = [[0.0; 3]; 3];
+                for row in &mut bounds {
+                    let line = self.require_line()?;
+                    let values: Result<Vec<f32>, _> =
+                        line.split_whitespace().map(str::parse).collect();
+                    match values {
+                        Ok(v) if v.len() == 2 + triclinic as usize => {
+                            row[..v.len()].copy_from_slice(&v)
+                        }
+                        _ => return Err(self.error(ErrorKind::InvalidBox(self.buf.clone()))),
+                    }
+                }
+                frame.cell = Cell::from_bounds(bounds, triclinic);
+            } else if let Some(columns) = item.strip_prefix("ATOMS") {
+                let count = count.ok_or_else(|| self.unexpected())?;
+                let columns: Vec<&str> = columns.split_whitespace().collect();
+                let layout = Layout::new(&columns, &self.options).map_err(|k| self.error(k))?;
+                self.read_atoms(&mut frame, &layout, count)?;
+                frame.sort_by_id();
+                return Ok(Some(frame));
+            } else if item == "TIME" || item == "UNITS" {
+                // single line items written by newer LAMMPS versions
+                self.require_line()?;
+            } else {
+                return Err(self.unexpected());
+            }
+            self.require_line()?;
+        }
+    }
+
+    fn read_atoms(
+        &mut self,
+        frame: &mut Frame,
+        layout: &Layout,
+        count: usize,
+    ) -> Result<(), Error> {
+        frame.atoms.reserve(count);
+        frame.properties = layout
+            .properties
+            .iter()
+            .map(|(name, _)| (name.clone(), Vec::with_capacity(count)))
+            .collect();
+
+        let mut spans = Vec::with_capacity(layout.len);
+        for _ in 0..count {
+            let line = self.require_line()?;
+            let atom = parse_atom(line, &mut spans, layout, &frame.cell, &mut frame.properties)
+                .map_err(|k| self.error(k))?;
+            frame.atoms.push(atom);
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Random access to the frames of a dump file, holding only the frame being read in
+/// memory. Opening scans the file once for where each frame starts.
+pub struct Dump {
+    file: BufReader<File>,
+    options: Options,
+    /// byte offset and line number of the first line of every frame
+    frames: Vec<(u64, usize)>,
+    /// in the first frame
+    atoms: usize,
+    lines: usize,
+}
+
+impl Dump {
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        let mut file = BufReader::new(File::open(path).map_err(Error::Io)?);
+        let mut frames = vec![];
+        let mut atoms = None;
+        let mut offset = 0;
+        let mut lines = 0;
+        let mut line = String::new();
+        let mut count_follows = false;
+        loop {
+            line.clear();
+            let read = file.read_line(&mut line).map_err(Error::Io)?;
+            if read == 0 {
+                break;
+            }
+            let trimmed = line.trim();
+            if count_follows {
+                atoms = trimmed.parse().ok();
+                count_follows = false;
+            } else if trimmed == "ITEM: TIMESTEP" {
+                frames.push((offset, lines));
+            } else if trimmed == "ITEM: NUMBER OF ATOMS" && atoms.is_none() {
+                count_follows = true;
+            }
+            offset += read as u64;
+            lines += 1;
+        }
+        Ok(Self {
+            file,
+            options,
+            frames,
+            atoms: atoms.unwrap_or(0),
+            lines,
+        })
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    pub fn atom_count(&self) -> usize {
+        self.atoms
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame(&mut self, n: usize) -> Result<Frame, Error> {
+        let no_such_frame = Error::Parse {
+            line: self.lines,
+            kind: ErrorKind::NoSuchFrame(n),
+        };
+        let &(offset, line) = self.frames.get(n).ok_or(no_such_frame)?;
+        self.file.seek(SeekFrom::Start(offset)).map_err(Error::Io)?;
+        let mut reader = Reader::new(&mut self.file, self.options.clone());
+        reader.line = line;
+        reader
+            .read_frame()?
+            .ok_or_else(|| reader.error(ErrorKind::Truncated))
+    }
+
+    /// Frame `n` styled by [`Options::style`].
+    pub fn atoms(&mut self, n: usize) -> Result<Vec<AtomCpu>, Error> {
+        Ok(self.frame(n)?.to_atoms(&self.options))
+    }
+
+    /// Overwrite the positions of `atoms` with frame `n`, keeping colors and radii.
+    pub fn read_atoms(&mut self, n: usize, atoms: &mut [AtomCpu]) -> Result<(), Error> {
+        let frame = self.frame(n)?;
+        if frame.atoms.len() != atoms.len() {
+            return Err(Error::Parse {
+                line: self.frames[n].1 + 1,
+                kind: ErrorKind::AtomCount {
+                    expected: atoms.len(),
+                    found: frame.atoms.len(),
+                },
+            });
+        }
+        for (atom, a) in atoms.iter_mut().zip(&frame.atoms) {
+            atom.pos = a.pos;
+        }
+        Ok(())
+    }
+}
+
+/// `spans` is scratch space, reused between lines to avoid an allocation per atom.
+fn parse_atom(
+    line: &str,
+    spans: &mut Vec<Range<usize>>,
+    layout: &Layout,
+    cell: &Cell,
+    properties: &mut [(String, Vec<f32>)],
+) -> Result<Atom, ErrorKind> {
+    spans.clear();
+    spans.extend(line.split_whitespace().map(|f| {
+        let start = f.as_ptr() as usize - line.as_ptr() as usize;
+        start..start + f.len()
+    }));
+    if spans.len() < layout.len {
+        return Err(ErrorKind::Truncated);
+    }
+    let field = |i: usize| &line[spans[i].clone()];
+    let parse = |i: usize, name: &str| {
+        field(i)
+            .parse::<f32>()
+            .map_err(|_| ErrorKind::InvalidField {
+                field: name.to_string(),
+                value: field(i).to_string(),
+            })
+    };
+
+    let mut pos = [
+        parse(layout.pos[0], "x")?,
+        parse(layout.pos[1], "y")?,
+        parse(layout.pos[2], "z")?,
+    ];
+    if layout.scaled {
+        pos = cell.fractional_to_cartesian(pos);
+    }
+    for ((name, i), (_, values)) in layout.properties.iter().zip(properties) {
+        values.push(parse(*i, name)?);
+    }
+    Ok(Atom {
+        id: layout.id.and_then(|i| field(i).parse().ok()),
+        type_: layout.type_.and_then(|i| field(i).parse().ok()),
+        element: layout.element.map(|i| field(i).to_string()),
+        pos,
+    })
+}
+
+pub fn parse(src: &str, options: Options) -> Result<Vec<Frame>, Error> {
+    Reader::new(src.as_bytes(), options).collect()
+}
+
+pub fn load(path: impl AsRef<Path>, options: Options) -> Result<Vec<Frame>, Error> {
+    Reader::open(path, options)?.collect()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnexpectedLine(l) => write!(f, "unexpected line {l:?}"),
+            ErrorKind::Truncated => write!(f, "file ends in the middle of a frame"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::NoCoordinates => {
+                write!(f, "no x y z, xu yu zu, xs ys zs or xsu ysu zsu columns")
+            }
+            ErrorKind::InvalidBox(l) => write!(f, "invalid box bounds {l:?}"),
+            ErrorKind::AtomCount { expected, found } => {
+                write!(f, "expected {expected} atoms, found {found}")
+            }
+            ErrorKind::NoSuchFrame(n) => write!(f, "no frame {n}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,5 +1,38 @@
 mod atom_renderer;
+pub mod bond_pipeline;
+mod bond_renderer;
+pub mod cache;
+pub mod camera;
+pub mod ccp4;
+pub mod cif;
+pub mod color;
+pub mod contour_pipeline;
+mod contour_renderer;
+pub mod cube;
+pub mod dcd;
+pub mod element;
+pub mod gltf;
 pub mod glue;
 mod gpubuf;
+pub mod gro;
+pub mod image;
+pub mod isosurface_pipeline;
+mod isosurface_renderer;
+pub mod lammps;
+pub mod mesh_export;
+pub mod mmcif;
+pub mod mol2;
+pub mod pdb;
+pub mod pqr;
+pub mod record;
 pub mod render;
 pub mod render_pipeline;
+pub mod sdf;
+pub mod session;
+pub mod smcif;
+pub mod sphere;
+pub mod stream;
+pub mod vasp;
+pub mod volume;
+pub mod xtc;
+pub mod xyz;
@@ -1,22 +1,540 @@
-use bddatoms::render::Render;
+use bddatoms::bond_pipeline;
+use bddatoms::cache::{self, Cache, Topology};
+use bddatoms::camera::Bounds;
+use bddatoms::ccp4;
+use bddatoms::color::Diverging;
+use bddatoms::cube;
+use bddatoms::dcd::Dcd;
+use bddatoms::gltf;
+use bddatoms::mesh_export;
+use bddatoms::mmcif::{self, ChainIds};
+use bddatoms::pdb::{Model, Pdb};
+use bddatoms::record::{self, Recorder};
+use bddatoms::render::{Headless, Render, TURN_SECONDS};
 use bddatoms::render_pipeline::AtomCpu;
+use bddatoms::session::{self, Override, Session};
+use bddatoms::stream::{Listener, Message};
+use bddatoms::{gro, lammps, mol2, pqr, sdf, smcif, vasp, xtc, xyz};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
-use winit::event::{KeyboardInput, VirtualKeyCode};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
 
-async fn run(event_loop: EventLoop<()>, window: Window) {
+async fn run(event_loop: EventLoop<()>, window: Window, args: &[String], listen: Option<&str>) {
     let window = Arc::new(window);
     let mut render = Render::create(Arc::clone(&window)).await;
 
-    let yellow = [0.3, 0.3, 0.1];
+    let mut scene = Scene::open(args, &mut render);
+    let mut live = listen.map(|address| Live {
+        listener: Listener::bind(address)
+            .unwrap_or_else(|e| panic!("failed to listen on {address}: {e}")),
+    });
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } => {
+            render.resize(size);
+        }
+        Event::RedrawRequested(_) => {
+            if let Some(live) = &mut live {
+                live.receive(&mut scene, &mut render);
+            }
+            render.update();
+            render.frame();
+        }
+        Event::RedrawEventsCleared => {
+            window.request_redraw();
+        }
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        }
+        | Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Q),
+                            ..
+                        },
+                    is_synthetic: false,
+                    ..
+                },
+            ..
+        } => *control_flow = ControlFlow::Exit,
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode:
+                                Some(key @ (VirtualKeyCode::Right | VirtualKeyCode::Left)),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            let step = if key == VirtualKeyCode::Right {
+                1
+            } else {
+                scene.frames.len() - 1
+            };
+            scene.current_frame = (scene.current_frame + step) % scene.frames.len();
+            scene.show(&mut render);
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::G),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            let atoms = scene.atoms();
+            match gltf::export(EXPORT_PATH, atoms, render.camera(), &Default::default()) {
+                Ok(()) => println!("exported {} atoms to {EXPORT_PATH}", atoms.len()),
+                Err(e) => eprintln!("failed to export {EXPORT_PATH}: {e}"),
+            }
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::S),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            if scene.streaming {
+                eprintln!("streamed atoms cannot be saved as a session");
+                return;
+            }
+            match session::save(SESSION_PATH, &scene.session(&render)) {
+                Ok(()) => println!("saved the session to {SESSION_PATH}"),
+                Err(e) => eprintln!("failed to save {SESSION_PATH}: {e}"),
+            }
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Space),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => render.set_spinning(!render.spinning()),
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::H),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => scene.toggle_highlight(&mut render),
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::P),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            let atoms = scene.atoms();
+            let options = mesh_export::Options {
+                union: true,
+                ..Default::default()
+            };
+            match mesh_export::export(PRINT_PATH, atoms, &options) {
+                Ok(()) => println!(
+                    "exported {} atoms to {PRINT_PATH}, in angstroms",
+                    atoms.len()
+                ),
+                Err(e) => eprintln!("failed to export {PRINT_PATH}: {e}"),
+            }
+        }
+        Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key @ (VirtualKeyCode::Up | VirtualKeyCode::Down)),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            // borrowed field by field so the center can change
+            let atoms = scene.frames.atoms(scene.current_frame);
+            if atoms.is_empty() {
+                return;
+            }
+            let n = match (scene.center_atom, key) {
+                (Some(i), VirtualKeyCode::Up) => (i + 1) % atoms.len(),
+                (Some(i), _) => (i + atoms.len() - 1) % atoms.len(),
+                (None, VirtualKeyCode::Up) => 0,
+                (None, _) => atoms.len() - 1,
+            };
+            scene.center_atom = Some(n);
+            render.contour_renderer_mut().set_center(atoms[n].pos);
+        }
+        _ => {}
+    });
+}
+
+/// Render the first frame offscreen and write it to `output`, without opening a window.
+async fn render_png(args: &[String], output: &str, options: &Headless) {
+    let mut render = Render::headless(options).await;
+    Scene::open(args, &mut render);
+    // in tiles, when the image is larger than the GPU can draw at once
+    let image = render.render_tiled(options.width, options.height).await;
+    image
+        .save_png(output)
+        .unwrap_or_else(|e| panic!("failed to write {output}: {e}"));
+}
+
+/// Command line flags, mostly for rendering without a window.
+#[derive(Default)]
+struct Flags {
+    /// Show atoms streamed to this address, see [`Listener::bind`].
+    listen: Option<String>,
+    headless: Headless,
+    /// Write one image here.
+    png: Option<String>,
+    /// Write what was loaded to a binary cache here, see [`cache`].
+    cache: Option<String>,
+    /// Write a movie, see [`record::Options`].
+    record: record::Options,
+    /// How many frames to record. Defaults to the whole trajectory, or one turn of the
+    /// view for a single structure.
+    frames: Option<u32>,
+}
+
+impl Flags {
+    /// Split out the flags, leaving the files to open in `args`.
+    fn parse(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut flags = Flags::default();
+        let mut rest = vec![];
+        let mut args_iter = std::mem::take(args).into_iter();
+        while let Some(arg) = args_iter.next() {
+            let mut value = || args_iter.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--listen" => flags.listen = Some(value()?),
+                "--png" => flags.png = Some(value()?),
+                "--cache" => flags.cache = Some(value()?),
+                "--record" => flags.record.png_directory = Some(value()?.into()),
+                "--video" => flags.record.video = Some(value()?.into()),
+                "--width" => {
+                    flags.headless.width = parse_flag::<NonZeroU32>(&arg, &value()?)?.get()
+                }
+                "--height" => {
+                    flags.headless.height = parse_flag::<NonZeroU32>(&arg, &value()?)?.get()
+                }
+                "--fps" => flags.record.fps = parse_flag::<NonZeroU32>(&arg, &value()?)?.get(),
+                "--frames" => flags.frames = Some(parse_flag(&arg, &value()?)?),
+                "--background" => {
+                    let color = value()?;
+                    let channels: Vec<f64> = color
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("--background: {e}"))?;
+                    flags.headless.background = channels
+                        .try_into()
+                        .map_err(|_| format!("--background takes r,g,b, not {color}"))?;
+                }
+                "--transparent" => flags.headless.transparent = true,
+                "--software" => flags.headless.software = true,
+                _ => rest.push(arg),
+            }
+        }
+        *args = rest;
+        flags.record.width = flags.headless.width;
+        flags.record.height = flags.headless.height;
+        Ok(flags)
+    }
+
+    fn recording(&self) -> bool {
+        self.record.png_directory.is_some() || self.record.video.is_some()
+    }
+}
+
+fn parse_flag<T: FromStr>(flag: &str, value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| format!("{flag}: {e}"))
+}
+
+/// Record a movie of the trajectory, or of the view turning around a single structure.
+async fn record_movie(args: &[String], flags: &Flags) -> io::Result<()> {
+    // before rendering anything, so bad options fail fast
+    let mut recorder = Recorder::new(flags.record.clone())?;
+    let mut render = Render::headless(&flags.headless).await;
+    let mut scene = Scene::open(args, &mut render);
+    let count = flags.frames.unwrap_or(match scene.frames.len() {
+        1 => (TURN_SECONDS * flags.record.fps as f32).round() as u32,
+        n => n as u32,
+    });
+    for n in 0..count as usize {
+        // one trajectory frame per movie frame, starting over if the movie is longer
+        if scene.frames.len() > 1 {
+            scene.current_frame = n % scene.frames.len();
+            scene.show(&mut render);
+        }
+        recorder.record(&mut render).await?;
+    }
+    recorder.finish()
+}
+
+/// Atoms a running simulation sends over a socket, see [`bddatoms::stream`].
+struct Live {
+    listener: Listener,
+}
+
+impl Live {
+    /// Show whatever has arrived since the last redraw. Never waits for more.
+    fn receive(&mut self, scene: &mut Scene, render: &mut Render) {
+        let mut received = false;
+        for message in self.listener.received() {
+            match message {
+                Ok(Message::Atoms(atoms)) => {
+                    render.set_bounds(Bounds::of(&atoms));
+                    scene.stream(atoms);
+                    received = true;
+                }
+                Ok(message @ Message::Positions { .. }) => {
+                    let (true, Frames::InMemory(frames)) = (scene.streaming, &mut scene.frames)
+                    else {
+                        eprintln!("stream: positions sent before any atoms");
+                        continue;
+                    };
+                    match message.apply(&mut frames[scene.current_frame]) {
+                        Ok(()) => received = true,
+                        Err(e) => eprintln!("stream: {e}"),
+                    }
+                }
+                Err(e) => eprintln!("stream: {e}"),
+            }
+        }
+        if received {
+            render.atom_renderer_mut().update_atoms(scene.atoms());
+            scene.show_bonds(render);
+        }
+    }
+}
+
+/// What is shown, and what it takes to save it as a session.
+struct Scene {
+    /// The command line it was opened from.
+    sources: Vec<String>,
+    frames: Frames,
+    current_frame: usize,
+    /// the atom picked with Up and Down, which the density map is contoured around
+    center_atom: Option<usize>,
+    overrides: Vec<Override>,
+    /// What each override replaced, to take it off again.
+    originals: Vec<Override>,
+    /// Pairs of bonded atoms, the same in every frame.
+    bonds: Vec<[u32; 2]>,
+    /// Showing atoms sent over a stream, which have no files to save as a session.
+    streaming: bool,
+}
+
+impl Scene {
+    /// Open whatever the command line names, or restore a saved session, and show it.
+    fn open(args: &[String], render: &mut Render) -> Self {
+        if let [path] = args {
+            if extension(path) == "session" {
+                let session =
+                    session::load(path).unwrap_or_else(|e| panic!("failed to load {path}: {e}"));
+                return Self::restore(session, render);
+            }
+        }
+        let mut topology = Topology::default();
+        let mut scene = Scene {
+            sources: args.to_vec(),
+            frames: open(args, render, &mut topology),
+            current_frame: 0,
+            center_atom: None,
+            overrides: vec![],
+            originals: vec![],
+            bonds: topology.bonds,
+            streaming: false,
+        };
+        scene.frame(render);
+        scene.show(render);
+        scene
+    }
+
+    fn restore(session: Session, render: &mut Render) -> Self {
+        // before opening, since surfaces are colored as they are made
+        render.set_style(&session.style);
+        let mut topology = Topology::default();
+        let mut frames = open(&session.sources, render, &mut topology);
+        let originals = session
+            .overrides
+            .iter()
+            .map(|o| o.undo(frames.atoms(0)))
+            .collect();
+        frames.apply_overrides(&session.overrides);
+        let mut scene = Scene {
+            current_frame: session.frame.min(frames.len() - 1),
+            sources: session.sources,
+            frames,
+            center_atom: session.center_atom,
+            overrides: session.overrides,
+            originals,
+            bonds: topology.bonds,
+            streaming: false,
+        };
+        scene.frame(render);
+        scene.show(render);
+        if let Some(atom) = scene.center_atom.and_then(|n| scene.atoms().get(n)) {
+            render.contour_renderer_mut().set_center(atom.pos);
+        }
+        render.set_transform(session.transform);
+        render.set_spinning(false);
+        scene
+    }
+
+    /// The first frame decides what the camera keeps in view, so that motion between
+    /// frames stays visible.
+    fn frame(&self, render: &mut Render) {
+        render.set_bounds(Bounds::of(self.frames.atoms(0)));
+    }
+
+    fn show(&mut self, render: &mut Render) {
+        self.frames.show(self.current_frame, render);
+        self.show_bonds(render);
+    }
+
+    /// Bonds follow the atoms of the frame shown.
+    fn show_bonds(&self, render: &mut Render) {
+        let bonds = bond_pipeline::bonds(self.atoms(), &self.bonds);
+        render.bond_renderer_mut().update_bonds(&bonds);
+    }
+
+    fn atoms(&self) -> &[AtomCpu] {
+        self.frames.atoms(self.current_frame)
+    }
+
+    /// Show streamed atoms in place of whatever was opened. Nothing about the old scene
+    /// applies to them, so it is all reset.
+    fn stream(&mut self, atoms: Vec<AtomCpu>) {
+        *self = Scene {
+            sources: vec![],
+            frames: Frames::InMemory(vec![atoms]),
+            current_frame: 0,
+            center_atom: None,
+            overrides: vec![],
+            originals: vec![],
+            bonds: vec![],
+            streaming: true,
+        };
+    }
 
+    /// Highlight the picked atom, or put it back as it was if it already is. Highlights
+    /// are saved with the session as overrides.
+    fn toggle_highlight(&mut self, render: &mut Render) {
+        let Some(n) = self.center_atom else {
+            return;
+        };
+        let change = match self.overrides.iter().position(|o| o.atom == n) {
+            Some(i) => {
+                self.overrides.remove(i);
+                self.originals.remove(i)
+            }
+            None => {
+                let highlight = Override {
+                    atom: n,
+                    color: Some(HIGHLIGHT_COLOR),
+                    radius: None,
+                };
+                self.originals.push(highlight.undo(self.atoms()));
+                self.overrides.push(highlight);
+                highlight
+            }
+        };
+        self.frames.apply_overrides(&[change]);
+        self.show(render);
+    }
+
+    fn session(&self, render: &Render) -> Session {
+        Session {
+            sources: self.sources.iter().map(|s| absolute(s)).collect(),
+            frame: self.current_frame,
+            center_atom: self.center_atom,
+            overrides: self.overrides.clone(),
+            transform: render.transform(),
+            style: render.style(),
+        }
+    }
+}
+
+/// Load whatever the command line names into `render`, and its topology where it has one.
+fn open(args: &[String], render: &mut Render, topology: &mut Topology) -> Frames {
+    match args {
+        [] => Frames::InMemory(vec![demo_atoms()]),
+        [path, rest @ ..] if matches!(extension(path).as_str(), "cube" | "cub") => {
+            let level = rest.first().map_or(Ok(DEFAULT_ISO_LEVEL), |l| l.parse());
+            let level = level.unwrap_or_else(|e| panic!("invalid isosurface level: {e}"));
+            open_cube(path, level, render).unwrap_or_else(|e| panic!("failed to load {path}: {e}"))
+        }
+        [path, map, rest @ ..] if matches!(extension(map).as_str(), "ccp4" | "map" | "mrc") => {
+            let sigma = rest.first().map_or(Ok(DEFAULT_SIGMA), |s| s.parse());
+            let sigma = sigma.unwrap_or_else(|e| panic!("invalid contour level: {e}"));
+            open_with_map(path, map, sigma, render, topology)
+                .unwrap_or_else(|e| panic!("failed to load {path} with {map}: {e}"))
+        }
+        [path, rest @ ..] => Frames::open(path, rest.first().map(String::as_str), topology)
+            .unwrap_or_else(|e| panic!("failed to load {path}: {e}")),
+    }
+}
+
+fn demo_atoms() -> Vec<AtomCpu> {
+    let yellow = [0.3, 0.3, 0.1];
     let brown = [0.15, 0.1, 0.05];
 
-    render.atom_renderer_mut().set_atoms(&[
+    vec![
         AtomCpu {
             pos: [0.0; 3],
             color: [0.4; 3],
@@ -62,46 +580,350 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
             color: [0.1, 0.1, 0.2],
             radius: 0.5,
         },
-    ]);
+    ]
+}
 
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent {
-            event: WindowEvent::Resized(size),
-            ..
-        } => {
-            render.resize(size);
+/// Load every frame (or model) in the file, filling in bonds and atom names for the
+/// formats that have them.
+fn load(path: &str, topology: &mut Topology) -> Result<Vec<Vec<AtomCpu>>, Box<dyn Error>> {
+    let frames: Vec<Vec<AtomCpu>> = match extension(path).as_str() {
+        "cif" | "mmcif" => {
+            let src = std::fs::read_to_string(path)?;
+            // small-molecule files give fractional coordinates, mmCIF gives Cartesian
+            if src.contains("_atom_site_fract_") || src.contains("_atom_site.fract_") {
+                smcif::parse(&src)?
+                    .iter()
+                    .map(|c| c.to_atoms(smcif::DEFAULT_TOLERANCE))
+                    .collect()
+            } else {
+                models(mmcif::parse(&src, ChainIds::Auth)?, topology)
+            }
         }
-        Event::RedrawRequested(_) => {
-            render.update();
-            render.frame();
+        // shown straight from the file by `Frames::open` instead
+        "bdda" => return Err("already a cache".into()),
+        "xyz" | "extxyz" => xyz::load(path)?.iter().map(xyz::Frame::to_atoms).collect(),
+        "sdf" | "sd" | "mol" => {
+            let molecules = sdf::load(path)?;
+            topology.bonds = shared_bonds(
+                molecules
+                    .iter()
+                    .map(|m| m.bonds.iter().map(|b| b.atoms.map(|i| i as u32)).collect()),
+            );
+            molecules.iter().map(sdf::Molecule::to_atoms).collect()
         }
-        Event::RedrawEventsCleared => {
-            window.request_redraw();
+        "gro" => gro::load(path)?.iter().map(gro::Frame::to_atoms).collect(),
+        "lammpstrj" | "dump" => {
+            let options = lammps::Options::default();
+            let frames = lammps::load(path, options.clone())?;
+            frames.iter().map(|f| f.to_atoms(&options)).collect()
         }
-        Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
+        // like PQR, colored by charge, unless the file has no charges
+        "mol2" => {
+            let molecules = mol2::load(path)?;
+            topology.bonds = shared_bonds(
+                molecules
+                    .iter()
+                    .map(|m| m.bonds.iter().map(|b| b.atoms.map(|i| i as u32)).collect()),
+            );
+            molecules
+                .iter()
+                .map(|m| {
+                    if m.has_charges() {
+                        m.to_atoms_by_charge(&Diverging::default())
+                    } else {
+                        m.to_atoms()
+                    }
+                })
+                .collect()
         }
-        | Event::WindowEvent {
-            event:
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            virtual_keycode: Some(VirtualKeyCode::Q),
-                            ..
-                        },
-                    is_synthetic: false,
-                    ..
-                },
-            ..
-        } => *control_flow = ControlFlow::Exit,
-        _ => {}
-    });
+        "pqr" => vec![pqr::load(path)?.to_atoms(&Diverging::default())],
+        "vasp" | "poscar" => vec![vasp::load_poscar(path)?.to_atoms()],
+        // VASP files are named by convention rather than extension
+        _ if file_name(path).starts_with("XDATCAR") => vasp::load_xdatcar(path)?
+            .iter()
+            .map(vasp::Structure::to_atoms)
+            .collect(),
+        _ if ["POSCAR", "CONTCAR"]
+            .iter()
+            .any(|name| file_name(path).starts_with(name)) =>
+        {
+            vec![vasp::load_poscar(path)?.to_atoms()]
+        }
+        _ => models(Pdb::load(path)?, topology),
+    };
+    if frames.is_empty() {
+        return Err("no atoms found".into());
+    }
+    Ok(frames)
+}
+
+/// A common choice for orbitals. Densities usually want something smaller.
+const DEFAULT_ISO_LEVEL: f32 = 0.02;
+
+/// Show the molecule in a cube file, with the first grid drawn as surfaces at `level`
+/// and `-level`.
+fn open_cube(path: &str, level: f32, render: &mut Render) -> Result<Frames, Box<dyn Error>> {
+    let cube = cube::load(path)?;
+    let atoms = cube.to_atoms();
+    if let Some(grid) = cube.grids.into_iter().next() {
+        render
+            .isosurface_renderer_mut()
+            .set_grid(&grid, &[level, -level]);
+    }
+    Ok(Frames::InMemory(vec![atoms]))
+}
+
+/// Where G writes the scene as glTF.
+const EXPORT_PATH: &str = "bddatoms.glb";
+
+/// Where S saves the session.
+const SESSION_PATH: &str = "bddatoms.session";
+
+/// What H paints the picked atom.
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 0.85, 0.1];
+
+/// Where P writes the atoms as one closed, printable mesh, in angstroms.
+const PRINT_PATH: &str = "bddatoms.ply";
+
+const DEFAULT_SIGMA: f32 = 1.0;
+
+/// How far from the center the density map is drawn, in angstroms.
+const CONTOUR_RADIUS: f32 = 10.0;
+
+/// Show a model with its density map contoured at `sigma` around the middle of the
+/// model. Up and Down move the contoured region from atom to atom.
+fn open_with_map(
+    path: &str,
+    map: &str,
+    sigma: f32,
+    render: &mut Render,
+    topology: &mut Topology,
+) -> Result<Frames, Box<dyn Error>> {
+    let map = ccp4::load(map)?;
+    let frames = Frames::open(path, None, topology)?;
+    let level = map.sigma_level(sigma);
+    let contours = render.contour_renderer_mut();
+    contours.set_center(Bounds::of(frames.atoms(0)).center.into());
+    contours.set_map(map.grid, level, CONTOUR_RADIUS);
+    Ok(frames)
+}
+
+/// Files by absolute path, so a saved session opens from any directory. Anything else,
+/// such as a level, is kept as given.
+fn absolute(arg: &str) -> String {
+    std::fs::canonicalize(arg)
+        .ok()
+        .and_then(|path| path.into_os_string().into_string().ok())
+        .unwrap_or_else(|| arg.to_string())
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_ascii_uppercase()
+}
+
+/// A cache with at least one model, and its topology.
+fn open_cache(path: &str, topology: &mut Topology) -> Result<Cache, Box<dyn Error>> {
+    let cache = Cache::open(path)?;
+    if cache.is_empty() {
+        return Err("no models found".into());
+    }
+    *topology = cache.topology.clone();
+    Ok(cache)
+}
+
+fn models(pdb: Pdb, topology: &mut Topology) -> Vec<Vec<AtomCpu>> {
+    *topology = Topology::from_pdb(&pdb);
+    pdb.models.iter().map(Model::to_atoms).collect()
+}
+
+/// Bonds are the same in every frame, so a file of several molecules only gets them when
+/// every molecule has the same ones, as conformers of one molecule do.
+fn shared_bonds(mut molecules: impl Iterator<Item = Vec<[u32; 2]>>) -> Vec<[u32; 2]> {
+    let Some(first) = molecules.next() else {
+        return vec![];
+    };
+    if molecules.all(|bonds| bonds == first) {
+        first
+    } else {
+        vec![]
+    }
+}
+
+enum Frames {
+    InMemory(Vec<Vec<AtomCpu>>),
+    /// Models are shown straight from the memory mapped cache.
+    Cached(Cache),
+    /// Positions are read from the trajectory as frames are shown. Colors and radii come
+    /// from a topology file, since most trajectories do not store them.
+    Trajectory {
+        trajectory: Trajectory,
+        atoms: Vec<AtomCpu>,
+    },
+}
+
+enum Trajectory {
+    Dcd(Dcd),
+    Xtc(xtc::Reader<BufReader<File>>),
+    Lammps(lammps::Dump),
+}
+
+impl Frames {
+    fn open(
+        path: &str,
+        topology_file: Option<&str>,
+        topology: &mut Topology,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut trajectory = match extension(path).as_str() {
+            "bdda" => return Ok(Frames::Cached(open_cache(path, topology)?)),
+            "dcd" => Trajectory::Dcd(Dcd::open(path)?),
+            "xtc" => Trajectory::Xtc(xtc::Reader::open(path)?),
+            "lammpstrj" | "dump" => {
+                Trajectory::Lammps(lammps::Dump::open(path, lammps::Options::default())?)
+            }
+            _ => return Ok(Frames::InMemory(load(path, topology)?)),
+        };
+
+        if trajectory.len() == 0 {
+            return Err("no frames found".into());
+        }
+        let mut atoms = if let Trajectory::Lammps(dump) = &mut trajectory {
+            // dumps have atom types, so they need no topology file
+            dump.atoms(0)?
+        } else {
+            let topology_file =
+                topology_file.ok_or("a trajectory needs a topology file as well")?;
+            if extension(topology_file) == "bdda" {
+                // only the first model, rather than a copy of every one
+                open_cache(topology_file, topology)?.model(0).to_vec()
+            } else {
+                load(topology_file, topology)?.swap_remove(0)
+            }
+        };
+        if atoms.len() != trajectory.atom_count() {
+            return Err(format!(
+                "{} has {} atoms but {path} has {}",
+                topology_file.unwrap_or(path),
+                atoms.len(),
+                trajectory.atom_count()
+            )
+            .into());
+        }
+        trajectory.read_atoms(0, &mut atoms)?;
+        Ok(Frames::Trajectory { trajectory, atoms })
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Frames::InMemory(frames) => frames.len(),
+            Frames::Cached(cache) => cache.len(),
+            Frames::Trajectory { trajectory, .. } => trajectory.len(),
+        }
+    }
+
+    /// The atoms of frame `n`, which for trajectories must be the frame shown last.
+    fn atoms(&self, n: usize) -> &[AtomCpu] {
+        match self {
+            Frames::InMemory(frames) => &frames[n],
+            Frames::Cached(cache) => cache.model(n),
+            Frames::Trajectory { atoms, .. } => atoms,
+        }
+    }
+
+    fn apply_overrides(&mut self, overrides: &[Override]) {
+        for n in 0..self.len() {
+            let atoms = match self {
+                Frames::InMemory(frames) => &mut frames[n],
+                Frames::Cached(cache) => cache.model_mut(n),
+                // trajectories only change positions, so the one set of atoms is enough
+                Frames::Trajectory { atoms, .. } if n == 0 => atoms,
+                Frames::Trajectory { .. } => break,
+            };
+            for o in overrides {
+                o.apply(atoms);
+            }
+        }
+    }
+
+    fn show(&mut self, n: usize, render: &mut Render) {
+        match self {
+            Frames::InMemory(frames) => render.atom_renderer_mut().set_atoms(&frames[n]),
+            Frames::Cached(cache) => render.atom_renderer_mut().set_atoms(cache.model(n)),
+            Frames::Trajectory { trajectory, atoms } => {
+                if let Err(e) = trajectory.read_atoms(n, atoms) {
+                    eprintln!("failed to read frame {n}: {e}");
+                    return;
+                }
+                render.atom_renderer_mut().update_atoms(atoms);
+            }
+        }
+    }
+}
+
+impl Trajectory {
+    fn atom_count(&self) -> usize {
+        match self {
+            Trajectory::Dcd(dcd) => dcd.atom_count(),
+            Trajectory::Xtc(xtc) => xtc.atom_count(),
+            Trajectory::Lammps(dump) => dump.atom_count(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Trajectory::Dcd(dcd) => dcd.len(),
+            Trajectory::Xtc(xtc) => xtc.len(),
+            Trajectory::Lammps(dump) => dump.len(),
+        }
+    }
+
+    fn read_atoms(&mut self, n: usize, atoms: &mut [AtomCpu]) -> Result<(), Box<dyn Error>> {
+        match self {
+            Trajectory::Dcd(dcd) => dcd.read_atoms(n, atoms)?,
+            Trajectory::Xtc(xtc) => xtc.read_atoms(n, atoms)?,
+            Trajectory::Lammps(dump) => dump.read_atoms(n, atoms)?,
+        }
+        Ok(())
+    }
 }
 
 fn main() {
     env_logger::init();
 
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let flags = Flags::parse(&mut args).unwrap_or_else(|e| panic!("{e}"));
+    if let Some(output) = &flags.cache {
+        let [path] = args.as_slice() else {
+            panic!("--cache takes a single structure file");
+        };
+        let mut topology = Topology::default();
+        let frames =
+            load(path, &mut topology).unwrap_or_else(|e| panic!("failed to load {path}: {e}"));
+        cache::save(output, &frames, &topology)
+            .unwrap_or_else(|e| panic!("failed to write {output}: {e}"));
+        return;
+    }
+    if let Some(output) = &flags.png {
+        pollster::block_on(render_png(&args, output, &flags.headless));
+        return;
+    }
+    if flags.recording() {
+        pollster::block_on(record_movie(&args, &flags))
+            .unwrap_or_else(|e| panic!("failed to record: {e}"));
+        return;
+    }
+
     let event_loop = EventLoop::new();
     let window = winit::window::WindowBuilder::new()
         .with_inner_size(winit::dpi::LogicalSize {
@@ -112,5 +934,5 @@ fn main() {
         .unwrap();
 
     // Temporarily avoid srgb formats for the swapchain on the web
-    pollster::block_on(run(event_loop, window));
+    pollster::block_on(run(event_loop, window, &args, flags.listen.as_deref()));
 }
@@ -0,0 +1,245 @@
+//! Export of atoms as triangle meshes in PLY, OBJ and binary STL, for 3D printing and
+//! for tools that cannot draw impostors.
+//!
+//! Atoms are either tessellated one sphere each, which leaves overlapping spheres
+//! intersecting, or unioned into one watertight surface: the zero level of a distance
+//! field sampled on a grid, extracted with marching cubes. Units are those of the atoms.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use glam::Vec3;
+
+use crate::{
+    render_pipeline::AtomCpu,
+    sphere,
+    volume::{Grid, Mesh},
+};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Detail of each sphere, see [`sphere::icosphere`]. When unioning this sets the
+    /// grid spacing instead, so that the detail is about the same, unless the grid would
+    /// be larger than [`MAX_GRID_POINTS`].
+    pub subdivisions: u32,
+    /// Merge overlapping spheres into one closed surface.
+    pub union: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            subdivisions: 2,
+            union: false,
+        }
+    }
+}
+
+/// Large structures are unioned on a coarser grid rather than one that does not fit in
+/// memory. This many points take about 128 MB while unioning.
+pub const MAX_GRID_POINTS: usize = 1 << 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ply,
+    Obj,
+    Stl,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ply" => Some(Format::Ply),
+            "obj" => Some(Format::Obj),
+            "stl" => Some(Format::Stl),
+            _ => None,
+        }
+    }
+}
+
+/// Tessellate `atoms` and write them in the format given by the extension of `path`.
+pub fn export(path: impl AsRef<Path>, atoms: &[AtomCpu], options: &Options) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a .ply, .obj or .stl file", path.display()),
+        )
+    })?;
+    let (mesh, colors) = tessellate(atoms, options);
+    let mut file = BufWriter::new(File::create(path)?);
+    match format {
+        Format::Ply => write_ply(&mut file, &mesh, &colors)?,
+        Format::Obj => write_obj(&mut file, &mesh, &colors)?,
+        Format::Stl => write_stl(&mut file, &mesh)?,
+    }
+    file.flush()
+}
+
+/// The atoms as one mesh, with a color for each vertex.
+pub fn tessellate(atoms: &[AtomCpu], options: &Options) -> (Mesh, Vec<[f32; 3]>) {
+    if options.union {
+        return union(atoms, options.subdivisions);
+    }
+    let sphere = sphere::icosphere(options.subdivisions);
+    let colors = atoms
+        .iter()
+        .flat_map(|a| std::iter::repeat_n(a.color, sphere.positions.len()))
+        .collect();
+    (sphere::bake(&sphere, atoms), colors)
+}
+
+/// Each point of the grid holds how far inside the nearest sphere surface it is, so the
+/// union of the spheres is everything above zero.
+fn union(atoms: &[AtomCpu], subdivisions: u32) -> (Mesh, Vec<[f32; 3]>) {
+    let Some(smallest) = atoms.iter().map(|a| a.radius).reduce(f32::min) else {
+        return Default::default();
+    };
+    let (min, max) = atoms.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), a| {
+            let pos = Vec3::from(a.pos);
+            (min.min(pos - a.radius), max.max(pos + a.radius))
+        },
+    );
+    // about the edge length of an icosphere with this many subdivisions
+    let mut spacing = smallest * 1.05 / 2f32.powi(subdivisions as i32);
+    // leave room around the atoms so the surface closes inside the grid
+    let shape = |spacing: f32| {
+        let shape = ((max - min) / spacing).ceil().as_uvec3() + 5;
+        shape.to_array().map(|n| n as usize)
+    };
+    while shape(spacing).iter().product::<usize>() > MAX_GRID_POINTS {
+        let points = shape(spacing).iter().product::<usize>() as f32;
+        spacing *= (points / MAX_GRID_POINTS as f32).cbrt().max(1.01);
+    }
+    let shape = shape(spacing);
+    let origin = min - 2.0 * spacing;
+    // far from every atom the distance is clamped, which changes nothing near the surface
+    let outside = -2.0 * spacing;
+
+    let mut grid = Grid {
+        shape,
+        origin: origin.into(),
+        axes: [
+            [spacing, 0.0, 0.0],
+            [0.0, spacing, 0.0],
+            [0.0, 0.0, spacing],
+        ],
+        values: vec![outside; shape.iter().product()],
+    };
+    // the atom each grid point is deepest inside of, for coloring
+    let mut owner = vec![0u32; grid.values.len()];
+
+    for (n, atom) in atoms.iter().enumerate() {
+        let center = Vec3::from(atom.pos);
+        let reach = atom.radius - outside;
+        let lo = ((center - reach - origin) / spacing)
+            .floor()
+            .max(Vec3::ZERO);
+        let hi = ((center + reach - origin) / spacing).ceil();
+        let [lo, hi] = [lo, hi].map(|v| v.to_array().map(|x| x as usize));
+        for i in lo[0]..=hi[0].min(shape[0] - 1) {
+            for j in lo[1]..=hi[1].min(shape[1] - 1) {
+                for k in lo[2]..=hi[2].min(shape[2] - 1) {
+                    let depth = atom.radius - grid.position([i, j, k]).distance(center);
+                    let index = grid.index([i, j, k]);
+                    if depth > grid.values[index] {
+                        grid.values[index] = depth;
+                        owner[index] = n as u32;
+                    }
+                }
+            }
+        }
+    }
+
+    let mesh = grid.isosurface(0.0);
+    let colors = mesh
+        .positions
+        .iter()
+        .map(|&p| {
+            let nearest = ((Vec3::from(p) - origin) / spacing).round();
+            let nearest = nearest.to_array().map(|x| x as usize);
+            atoms[owner[grid.index(nearest)] as usize].color
+        })
+        .collect();
+    (mesh, colors)
+}
+
+/// Binary PLY with per-vertex normals and colors.
+pub fn write_ply(mut w: impl Write, mesh: &Mesh, colors: &[[f32; 3]]) -> io::Result<()> {
+    let triangles = mesh.indices.len() / 3;
+    write!(
+        w,
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         comment written by bddatoms\n\
+         element vertex {}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         property uchar red\nproperty uchar green\nproperty uchar blue\n\
+         element face {triangles}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n",
+        mesh.positions.len()
+    )?;
+    for ((pos, normal), color) in mesh.positions.iter().zip(&mesh.normals).zip(colors) {
+        for v in pos.iter().chain(normal) {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        w.write_all(&color.map(to_byte))?;
+    }
+    for triangle in mesh.indices.chunks(3) {
+        w.write_all(&[3])?;
+        for i in triangle {
+            w.write_all(&i.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// OBJ with vertex colors after the positions, an extension most tools read.
+pub fn write_obj(mut w: impl Write, mesh: &Mesh, colors: &[[f32; 3]]) -> io::Result<()> {
+    writeln!(w, "# written by bddatoms")?;
+    for ([x, y, z], [r, g, b]) in mesh.positions.iter().zip(colors) {
+        writeln!(w, "v {x} {y} {z} {r} {g} {b}")?;
+    }
+    for [x, y, z] in &mesh.normals {
+        writeln!(w, "vn {x} {y} {z}")?;
+    }
+    // indices start at one
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i + 1);
+        writeln!(w, "f {a}//{a} {b}//{b} {c}//{c}")?;
+    }
+    Ok(())
+}
+
+/// Binary STL. It has no colors, and normals are per face.
+pub fn write_stl(mut w: impl Write, mesh: &Mesh) -> io::Result<()> {
+    let mut header = [0; 80];
+    header[..19].copy_from_slice(b"written by bddatoms");
+    w.write_all(&header)?;
+    w.write_all(&((mesh.indices.len() / 3) as u32).to_le_bytes())?;
+    for triangle in mesh.indices.chunks(3) {
+        let [a, b, c] =
+            [triangle[0], triangle[1], triangle[2]].map(|i| Vec3::from(mesh.positions[i as usize]));
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for v in [normal, a, b, c] {
+            for x in v.to_array() {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        // attribute byte count, unused
+        w.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+fn to_byte(channel: f32) -> u8 {
+    (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+}
@@ -0,0 +1,227 @@
+//! Reader for the `_atom_site` category of mmCIF (PDBx) files.
+//!
+//! Atoms are collected into the same [`Pdb`] structure produced by the PDB reader, so
+//! both formats share the conversion to `AtomCpu`.
+
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use crate::{
+    cif::{Error, ErrorKind, Token, Tokenizer, Value},
+    pdb::{Atom, Model, Pdb, Record},
+};
+
+/// mmCIF carries two sets of identifiers. `Auth` matches what the depositors used and
+/// what a PDB file of the same entry would show, `Label` is the archive's own numbering.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChainIds {
+    #[default]
+    Auth,
+    Label,
+}
+
+pub fn load(path: impl AsRef<Path>, ids: ChainIds) -> Result<Pdb, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?, ids)
+}
+
+pub fn parse(src: &str, ids: ChainIds) -> Result<Pdb, Error> {
+    let mut tokens = Tokenizer::new(src);
+    let mut pdb = Pdb::default();
+    let mut pending = None;
+
+    while let Some(token) = pending
+        .take()
+        .map(Ok)
+        .or_else(|| tokens.next())
+        .transpose()?
+    {
+        if token != Token::Loop {
+            continue;
+        }
+
+        let mut tags = vec![];
+        let mut next = tokens.next().transpose()?;
+        while let Some(Token::Tag(tag)) = next {
+            tags.push(tag);
+            next = tokens.next().transpose()?;
+        }
+
+        let columns = tags
+            .first()
+            .filter(|t| is_atom_site(t))
+            .map(|_| Columns::new(&tags, ids))
+            .transpose()
+            .map_err(|kind| tokens.error(kind))?;
+
+        let mut row = Vec::with_capacity(tags.len());
+        while let Some(Token::Value(value)) = next {
+            if let Some(columns) = &columns {
+                row.push(value);
+                if row.len() == tags.len() {
+                    let (model, atom) = columns.atom(&row).map_err(|kind| tokens.error(kind))?;
+                    push_atom(&mut pdb, model, atom);
+                    row.clear();
+                }
+            }
+            next = tokens.next().transpose()?;
+        }
+        if !row.is_empty() {
+            return Err(tokens.error(ErrorKind::RaggedLoop));
+        }
+        pending = next;
+    }
+
+    Ok(pdb)
+}
+
+fn is_atom_site(tag: &str) -> bool {
+    atom_site_field(tag).is_some()
+}
+
+/// The part of an `_atom_site` tag after the category, matched without regard to case
+/// as CIF tags are.
+fn atom_site_field(tag: &str) -> Option<&str> {
+    const PREFIX: &str = "_atom_site.";
+    let (prefix, field) = (tag.get(..PREFIX.len())?, tag.get(PREFIX.len()..)?);
+    prefix.eq_ignore_ascii_case(PREFIX).then_some(field)
+}
+
+fn push_atom(pdb: &mut Pdb, model: Option<u32>, atom: Atom) {
+    match pdb.models.last_mut() {
+        Some(last) if last.serial == model => last.atoms.push(atom),
+        _ => pdb.models.push(Model {
+            serial: model,
+            atoms: vec![atom],
+        }),
+    }
+}
+
+/// Index of each `_atom_site` column of interest within a loop row.
+struct Columns {
+    group: Option<usize>,
+    id: usize,
+    type_symbol: Option<usize>,
+    atom_id: usize,
+    alt_id: Option<usize>,
+    comp_id: usize,
+    asym_id: usize,
+    seq_id: Option<usize>,
+    /// used when the preferred `seq_id` is `.`, as with label numbering of waters
+    fallback_seq_id: Option<usize>,
+    ins_code: Option<usize>,
+    xyz: [usize; 3],
+    occupancy: Option<usize>,
+    b_iso: Option<usize>,
+    formal_charge: Option<usize>,
+    model_num: Option<usize>,
+}
+
+impl Columns {
+    fn new(tags: &[&str], ids: ChainIds) -> Result<Self, ErrorKind> {
+        let index: HashMap<String, usize> = tags
+            .iter()
+            .enumerate()
+            .map(|(i, t)| match atom_site_field(t) {
+                Some(field) => Ok((field.to_ascii_lowercase(), i)),
+                None => Err(ErrorKind::ForeignTag(t.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+        let opt = |name: &str| index.get(name).copied();
+        let req = |name: &'static str| {
+            atom_site_field(name)
+                .and_then(opt)
+                .ok_or(ErrorKind::MissingTag(name))
+        };
+        let (preferred, other) = match ids {
+            ChainIds::Auth => ("auth_", "label_"),
+            ChainIds::Label => ("label_", "auth_"),
+        };
+        let either = |field: &'static str, tag: &'static str| {
+            opt(&format!("{preferred}{field}"))
+                .or_else(|| opt(&format!("{other}{field}")))
+                .ok_or(ErrorKind::MissingTag(tag))
+        };
+
+        Ok(Self {
+            group: opt("group_pdb"),
+            id: req("_atom_site.id")?,
+            type_symbol: opt("type_symbol"),
+            atom_id: either("atom_id", "_atom_site.auth_atom_id")?,
+            alt_id: opt("label_alt_id"),
+            comp_id: either("comp_id", "_atom_site.auth_comp_id")?,
+            asym_id: either("asym_id", "_atom_site.auth_asym_id")?,
+            seq_id: opt(&format!("{preferred}seq_id")),
+            fallback_seq_id: opt(&format!("{other}seq_id")),
+            ins_code: opt("pdbx_pdb_ins_code"),
+            xyz: [
+                req("_atom_site.cartn_x")?,
+                req("_atom_site.cartn_y")?,
+                req("_atom_site.cartn_z")?,
+            ],
+            occupancy: opt("occupancy"),
+            b_iso: opt("b_iso_or_equiv"),
+            formal_charge: opt("pdbx_formal_charge"),
+            model_num: opt("pdbx_pdb_model_num"),
+        })
+    }
+
+    fn atom(&self, row: &[Value]) -> Result<(Option<u32>, Atom), ErrorKind> {
+        let text = |i: usize| row[i].non_null();
+        let opt_text = |i: Option<usize>| i.and_then(text);
+        let num = |i: usize, tag: &'static str| parse_value::<f32>(row[i], tag);
+
+        let record = match opt_text(self.group) {
+            Some(g) if g.eq_ignore_ascii_case("HETATM") => Record::Hetatm,
+            _ => Record::Atom,
+        };
+        let name = text(self.atom_id).unwrap_or_default().to_string();
+        let element = match opt_text(self.type_symbol) {
+            Some(e) => e.to_string(),
+            None => name.chars().take(1).collect(),
+        };
+        let res_seq = opt_parse(row, self.seq_id, "_atom_site.seq_id")?;
+        let res_seq = match res_seq {
+            Some(s) => s,
+            None => opt_parse(row, self.fallback_seq_id, "_atom_site.seq_id")?.unwrap_or(0),
+        };
+
+        let atom = Atom {
+            record,
+            serial: parse_value(row[self.id], "_atom_site.id")?,
+            name,
+            alt_loc: opt_text(self.alt_id).and_then(|a| a.chars().next()),
+            res_name: text(self.comp_id).unwrap_or_default().to_string(),
+            chain_id: text(self.asym_id).unwrap_or_default().to_string(),
+            res_seq,
+            i_code: opt_text(self.ins_code).and_then(|c| c.chars().next()),
+            pos: [
+                num(self.xyz[0], "_atom_site.Cartn_x")?,
+                num(self.xyz[1], "_atom_site.Cartn_y")?,
+                num(self.xyz[2], "_atom_site.Cartn_z")?,
+            ],
+            occupancy: opt_parse(row, self.occupancy, "_atom_site.occupancy")?.unwrap_or(1.0),
+            temp_factor: opt_parse(row, self.b_iso, "_atom_site.B_iso_or_equiv")?.unwrap_or(0.0),
+            element,
+            charge: opt_parse(row, self.formal_charge, "_atom_site.pdbx_formal_charge")?
+                .unwrap_or(0),
+        };
+        let model = opt_parse(row, self.model_num, "_atom_site.pdbx_PDB_model_num")?;
+        Ok((model, atom))
+    }
+}
+
+fn opt_parse<T: FromStr>(
+    row: &[Value],
+    column: Option<usize>,
+    tag: &'static str,
+) -> Result<Option<T>, ErrorKind> {
+    column
+        .and_then(|i| row[i].non_null().map(|_| parse_value(row[i], tag)))
+        .transpose()
+}
+
+fn parse_value<T: FromStr>(value: Value, tag: &'static str) -> Result<T, ErrorKind> {
+    value.text.parse().map_err(|_| ErrorKind::InvalidValue {
+        tag,
+        value: value.text.to_string(),
+    })
+}
@@ -0,0 +1,308 @@
+//! Reader for Tripos MOL2 files. The `MOLECULE`, `ATOM`, `BOND` and `SUBSTRUCTURE`
+//! sections are interpreted, other sections are skipped. A file may hold many molecules,
+//! as docking programs write one per pose.
+
+use std::{collections::HashMap, fmt, io, path::Path, str::FromStr};
+
+use crate::{color::Diverging, element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Molecule {
+    pub name: String,
+    /// `SMALL`, `PROTEIN`, ...
+    pub mol_type: String,
+    /// `GASTEIGER`, `USER_CHARGES`, `NO_CHARGES`, ...
+    pub charge_type: String,
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<Bond>,
+    pub substructures: Vec<Substructure>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub id: u32,
+    pub name: String,
+    /// angstroms
+    pub pos: [f32; 3],
+    /// Sybyl atom type such as `C.ar` or `N.pl3`.
+    pub sybyl_type: String,
+    pub subst_id: Option<u32>,
+    pub subst_name: Option<String>,
+    pub charge: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bond {
+    /// indices into `Molecule::atoms`
+    pub atoms: [usize; 2],
+    pub kind: BondType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BondType {
+    Single,
+    Double,
+    Triple,
+    Amide,
+    Aromatic,
+    Dummy,
+    Unknown,
+    NotConnected,
+}
+
+#[derive(Clone, Debug)]
+pub struct Substructure {
+    pub id: u32,
+    pub name: String,
+    /// index into `Molecule::atoms`
+    pub root_atom: usize,
+    /// `RESIDUE`, `GROUP`, ...
+    pub kind: Option<String>,
+    pub chain: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// Data before the first `@<TRIPOS>MOLECULE`.
+    NoMolecule,
+    MissingField(&'static str),
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    NoSuchAtom(u32),
+}
+
+impl Molecule {
+    /// Colored by element, which is taken from the Sybyl type.
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms
+            .iter()
+            .map(|a| {
+                let element = a.element();
+                AtomCpu {
+                    pos: a.pos,
+                    color: element.color,
+                    radius: element.radius,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the atoms carry partial charges. Files without them give zeros.
+    pub fn has_charges(&self) -> bool {
+        !self.charge_type.eq_ignore_ascii_case("NO_CHARGES")
+            && self.atoms.iter().any(|a| a.charge != 0.0)
+    }
+
+    /// Colored by partial charge.
+    pub fn to_atoms_by_charge(&self, colors: &Diverging) -> Vec<AtomCpu> {
+        self.atoms
+            .iter()
+            .map(|a| AtomCpu {
+                pos: a.pos,
+                color: colors.color(a.charge),
+                radius: a.element().radius,
+            })
+            .collect()
+    }
+}
+
+impl Atom {
+    /// The element part of the Sybyl type, `C` for `C.ar`.
+    pub fn element(&self) -> &'static element::Element {
+        let symbol = self.sybyl_type.split('.').next().unwrap_or_default();
+        element::lookup_or_unknown(symbol)
+    }
+}
+
+impl FromStr for BondType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(match s {
+            "1" => BondType::Single,
+            "2" => BondType::Double,
+            "3" => BondType::Triple,
+            "am" => BondType::Amide,
+            "ar" => BondType::Aromatic,
+            "du" => BondType::Dummy,
+            "un" => BondType::Unknown,
+            "nc" => BondType::NotConnected,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Molecule,
+    Atom,
+    Bond,
+    Substructure,
+    Other,
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Molecule>, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+}
+
+pub fn parse(src: &str) -> Result<Vec<Molecule>, Error> {
+    let mut molecules: Vec<Molecule> = vec![];
+    let mut section = Section::Other;
+    // position within the MOLECULE section, which is line oriented
+    let mut header_line = 0;
+    let mut ids = HashMap::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let err = |kind| Error::Parse { line: i + 1, kind };
+
+        if let Some(name) = line.trim().strip_prefix("@<TRIPOS>") {
+            section = match name {
+                "MOLECULE" => {
+                    molecules.push(Molecule::default());
+                    ids.clear();
+                    header_line = 0;
+                    Section::Molecule
+                }
+                "ATOM" => Section::Atom,
+                "BOND" => Section::Bond,
+                "SUBSTRUCTURE" => Section::Substructure,
+                _ => Section::Other,
+            };
+            continue;
+        }
+
+        if section == Section::Other || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let molecule = molecules
+            .last_mut()
+            .ok_or_else(|| err(ErrorKind::NoMolecule))?;
+
+        match section {
+            Section::Molecule => {
+                match header_line {
+                    0 => molecule.name = line.trim().to_string(),
+                    2 => molecule.mol_type = line.trim().to_string(),
+                    3 => molecule.charge_type = line.trim().to_string(),
+                    // counts are implied by the sections that follow
+                    _ => {}
+                }
+                header_line += 1;
+            }
+            _ if line.trim().is_empty() => {}
+            Section::Atom => {
+                let atom = parse_atom(line).map_err(err)?;
+                ids.insert(atom.id, molecule.atoms.len());
+                molecule.atoms.push(atom);
+            }
+            Section::Bond => {
+                let bond = parse_bond(line, &ids).map_err(err)?;
+                molecule.bonds.push(bond);
+            }
+            Section::Substructure => {
+                let substructure = parse_substructure(line, &ids).map_err(err)?;
+                molecule.substructures.push(substructure);
+            }
+            Section::Other => unreachable!(),
+        }
+    }
+
+    Ok(molecules)
+}
+
+/// `atom_id atom_name x y z atom_type [subst_id [subst_name [charge [status_bit]]]]`
+fn parse_atom(line: &str) -> Result<Atom, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    Ok(Atom {
+        id: required(fields.next(), "atom_id")?,
+        name: required(fields.next(), "atom_name")?,
+        pos: [
+            required(fields.next(), "x")?,
+            required(fields.next(), "y")?,
+            required(fields.next(), "z")?,
+        ],
+        sybyl_type: required(fields.next(), "atom_type")?,
+        subst_id: optional(fields.next(), "subst_id")?,
+        subst_name: fields.next().map(str::to_string),
+        charge: optional(fields.next(), "charge")?.unwrap_or(0.0),
+    })
+}
+
+/// `bond_id origin_atom_id target_atom_id bond_type [status_bits]`
+fn parse_bond(line: &str, ids: &HashMap<u32, usize>) -> Result<Bond, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    let _id: u32 = required(fields.next(), "bond_id")?;
+    let origin = atom_index(required(fields.next(), "origin_atom_id")?, ids)?;
+    let target = atom_index(required(fields.next(), "target_atom_id")?, ids)?;
+    Ok(Bond {
+        atoms: [origin, target],
+        kind: required(fields.next(), "bond_type")?,
+    })
+}
+
+/// `subst_id subst_name root_atom [subst_type [dict_type [chain ...]]]`
+fn parse_substructure(line: &str, ids: &HashMap<u32, usize>) -> Result<Substructure, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    let id = required(fields.next(), "subst_id")?;
+    let name = required(fields.next(), "subst_name")?;
+    let root_atom = atom_index(required(fields.next(), "root_atom")?, ids)?;
+    let kind = fields.next().map(str::to_string);
+    let _dict_type = fields.next();
+    // `****` marks an empty field
+    let chain = fields.next().filter(|c| *c != "****").map(str::to_string);
+    Ok(Substructure {
+        id,
+        name,
+        root_atom,
+        kind,
+        chain,
+    })
+}
+
+fn atom_index(id: u32, ids: &HashMap<u32, usize>) -> Result<usize, ErrorKind> {
+    ids.get(&id).copied().ok_or(ErrorKind::NoSuchAtom(id))
+}
+
+fn required<T: FromStr>(value: Option<&str>, field: &'static str) -> Result<T, ErrorKind> {
+    let value = value.ok_or(ErrorKind::MissingField(field))?;
+    value.parse().map_err(|_| ErrorKind::InvalidField {
+        field,
+        value: value.to_string(),
+    })
+}
+
+fn optional<T: FromStr>(value: Option<&str>, field: &'static str) -> Result<Option<T>, ErrorKind> {
+    value.map(|v| required(Some(v), field)).transpose()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::NoMolecule => write!(f, "data before @<TRIPOS>MOLECULE"),
+            ErrorKind::MissingField(field) => write!(f, "missing {field}"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::NoSuchAtom(id) => write!(f, "reference to missing atom {id}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
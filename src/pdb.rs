@@ -0,0 +1,340 @@
+//! Reader for the fixed-column Protein Data Bank format.
+//!
+//! Only coordinate related records are interpreted (`MODEL`, `ENDMDL`, `ATOM`, `HETATM`
+//! and `CONECT`). Everything else is skipped.
+
+use std::{collections::HashSet, fmt, io, ops::Range, path::Path};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Pdb {
+    /// Files without `MODEL` records produce a single model.
+    pub models: Vec<Model>,
+    /// Pairs of atom serial numbers, each listed once with the lower serial first.
+    pub bonds: Vec<(u32, u32)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Model {
+    pub serial: Option<u32>,
+    pub atoms: Vec<Atom>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Record {
+    Atom,
+    Hetatm,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub record: Record,
+    pub serial: u32,
+    pub name: String,
+    pub alt_loc: Option<char>,
+    pub res_name: String,
+    pub chain_id: String,
+    pub res_seq: i32,
+    pub i_code: Option<char>,
+    /// angstroms
+    pub pos: [f32; 3],
+    pub occupancy: f32,
+    pub temp_factor: f32,
+    pub element: String,
+    pub charge: i8,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    NonAscii,
+    /// The line ended before a required field.
+    Truncated {
+        field: &'static str,
+    },
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    NestedModel,
+    UnmatchedEndmdl,
+}
+
+impl Pdb {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    /// The atoms of the first model, ready for `AtomRenderer::set_atoms`.
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.models.first().map(Model::to_atoms).unwrap_or_default()
+    }
+}
+
+impl Model {
+    /// Atoms with alternate locations reduced to one conformer. For each atom the first
+    /// alternate location listed in the file wins.
+    pub fn primary_atoms(&self) -> impl Iterator<Item = &Atom> {
+        let mut seen = HashSet::new();
+        self.atoms.iter().filter(move |a| match a.alt_loc {
+            None => true,
+            Some(_) => seen.insert((a.chain_id.as_str(), a.res_seq, a.i_code, a.name.as_str())),
+        })
+    }
+
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.primary_atoms().map(Atom::to_atom_cpu).collect()
+    }
+}
+
+impl Atom {
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = element::lookup_or_unknown(&self.element);
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+pub fn parse(src: &str) -> Result<Pdb, Error> {
+    let mut pdb = Pdb::default();
+    let mut current: Option<Model> = None;
+    let mut bonds = HashSet::new();
+
+    for (i, line) in src.lines().enumerate() {
+        let line_number = i + 1;
+        let err = |kind| Error::Parse {
+            line: line_number,
+            kind,
+        };
+        let line = Line(line);
+
+        match line.record_name() {
+            "MODEL" => {
+                if current.is_some() {
+                    return Err(err(ErrorKind::NestedModel));
+                }
+                let serial = line.opt(10..14);
+                let serial = serial
+                    .map(|s| s.parse().map_err(|_| invalid("serial", s)))
+                    .transpose()
+                    .map_err(err)?;
+                current = Some(Model {
+                    serial,
+                    atoms: vec![],
+                });
+            }
+            "ENDMDL" => {
+                let model = current
+                    .take()
+                    .ok_or_else(|| err(ErrorKind::UnmatchedEndmdl))?;
+                pdb.models.push(model);
+            }
+            "ATOM" | "HETATM" => {
+                line.check_ascii().map_err(err)?;
+                let atom = parse_atom(&line).map_err(err)?;
+                match &mut current {
+                    Some(model) => model.atoms.push(atom),
+                    // atoms outside of a MODEL block belong to an implicit single model
+                    None => match pdb.models.last_mut() {
+                        Some(model) if model.serial.is_none() => model.atoms.push(atom),
+                        _ => pdb.models.push(Model {
+                            serial: None,
+                            atoms: vec![atom],
+                        }),
+                    },
+                }
+            }
+            "CONECT" => {
+                line.check_ascii().map_err(err)?;
+                let from: u32 = line.num(6..11, "serial").map_err(err)?;
+                for range in [11..16, 16..21, 21..26, 26..31] {
+                    if let Some(s) = line.opt(range) {
+                        let to: u32 = s.parse().map_err(|_| err(invalid("bonded serial", s)))?;
+                        bonds.insert((from.min(to), from.max(to)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // tolerate a missing final ENDMDL
+    pdb.models.extend(current);
+
+    pdb.bonds = bonds.into_iter().collect();
+    pdb.bonds.sort_unstable();
+
+    Ok(pdb)
+}
+
+fn parse_atom(line: &Line) -> Result<Atom, ErrorKind> {
+    let record = if line.record_name() == "ATOM" {
+        Record::Atom
+    } else {
+        Record::Hetatm
+    };
+    let raw_name = line.get(12..16, "name")?;
+    let name = raw_name.trim().to_string();
+    let res_name = line.get(17..20, "resName")?.trim().to_string();
+    let element = match line.opt(76..78) {
+        Some(e) => e.to_string(),
+        None => element_from_name(raw_name, record),
+    };
+    let charge = match line.opt(78..80) {
+        Some(c) => parse_charge(c).ok_or_else(|| invalid("charge", c))?,
+        None => 0,
+    };
+
+    Ok(Atom {
+        record,
+        serial: line.num(6..11, "serial")?,
+        name,
+        alt_loc: line.char(16),
+        res_name,
+        chain_id: line.opt(21..22).unwrap_or_default().to_string(),
+        res_seq: line.num(22..26, "resSeq")?,
+        i_code: line.char(26),
+        pos: [
+            line.num(30..38, "x")?,
+            line.num(38..46, "y")?,
+            line.num(46..54, "z")?,
+        ],
+        occupancy: line.opt_num(54..60, "occupancy")?.unwrap_or(1.0),
+        temp_factor: line.opt_num(60..66, "tempFactor")?.unwrap_or(0.0),
+        element,
+        charge,
+    })
+}
+
+/// Guess the element when columns 77-78 are blank. Names of single letter elements are
+/// conventionally right aligned into column 14, so a name starting in column 13 usually
+/// means a two letter element, except for hydrogens in standard residues. Hydrogens of
+/// ligands are left aligned as well when their names are long, such as `HG11`, so a
+/// name starting with H is only a two letter element when nothing follows, as for a
+/// mercury ion.
+fn element_from_name(raw_name: &str, record: Record) -> String {
+    let bytes = raw_name.as_bytes();
+    let first = bytes.first().copied().unwrap_or(b' ');
+    if first == b' ' || first.is_ascii_digit() {
+        return raw_name
+            .chars()
+            .find(char::is_ascii_alphabetic)
+            .map(String::from)
+            .unwrap_or_default();
+    }
+    let two = raw_name.get(..2).unwrap_or(raw_name);
+    let rest = raw_name.get(2..).unwrap_or_default().trim();
+    let hydrogen = first == b'H' && !rest.is_empty();
+    if record == Record::Hetatm && !hydrogen && element::lookup(two).is_some() {
+        two.to_string()
+    } else {
+        (first as char).to_string()
+    }
+}
+
+/// Charges are written as "2+" or "1-".
+fn parse_charge(s: &str) -> Option<i8> {
+    let (magnitude, sign) = s.split_at(s.len() - 1);
+    let magnitude: i8 = magnitude.trim().parse().ok()?;
+    match sign {
+        "+" => Some(magnitude),
+        "-" => Some(-magnitude),
+        _ => None,
+    }
+}
+
+fn invalid(field: &'static str, value: &str) -> ErrorKind {
+    ErrorKind::InvalidField {
+        field,
+        value: value.to_string(),
+    }
+}
+
+/// A line indexed by zero based column. Free text records such as `REMARK` may hold any
+/// UTF-8, so records read field by field are checked to be ascii before slicing.
+struct Line<'a>(&'a str);
+
+impl<'a> Line<'a> {
+    fn check_ascii(&self) -> Result<(), ErrorKind> {
+        if self.0.is_ascii() {
+            Ok(())
+        } else {
+            Err(ErrorKind::NonAscii)
+        }
+    }
+
+    fn record_name(&self) -> &'a str {
+        self.0.get(..6).unwrap_or(self.0).trim_end()
+    }
+
+    /// The trimmed field, or None if it is blank or past the end of the line.
+    fn opt(&self, range: Range<usize>) -> Option<&'a str> {
+        let end = range.end.min(self.0.len());
+        let s = self.0.get(range.start..end)?.trim();
+        (!s.is_empty()).then_some(s)
+    }
+
+    fn get(&self, range: Range<usize>, field: &'static str) -> Result<&'a str, ErrorKind> {
+        if self.0.len() <= range.start {
+            return Err(ErrorKind::Truncated { field });
+        }
+        Ok(&self.0[range.start..range.end.min(self.0.len())])
+    }
+
+    fn char(&self, column: usize) -> Option<char> {
+        self.opt(column..column + 1)?.chars().next()
+    }
+
+    fn num<T: std::str::FromStr>(
+        &self,
+        range: Range<usize>,
+        field: &'static str,
+    ) -> Result<T, ErrorKind> {
+        let s = self.get(range, field)?.trim();
+        s.parse().map_err(|_| invalid(field, s))
+    }
+
+    fn opt_num<T: std::str::FromStr>(
+        &self,
+        range: Range<usize>,
+        field: &'static str,
+    ) -> Result<Option<T>, ErrorKind> {
+        self.opt(range)
+            .map(|s| s.parse().map_err(|_| invalid(field, s)))
+            .transpose()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::NonAscii => write!(f, "non-ascii characters"),
+            ErrorKind::Truncated { field } => write!(f, "line ends before field {field}"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::NestedModel => write!(f, "MODEL inside another MODEL"),
+            ErrorKind::UnmatchedEndmdl => write!(f, "ENDMDL without MODEL"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
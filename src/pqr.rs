@@ -0,0 +1,220 @@
+//! Reader for PQR files as written by PDB2PQR and read by APBS: PDB-like atom records
+//! where the occupancy and temperature factor columns are replaced by charge and radius.
+//!
+//! Fields are separated by whitespace rather than fixed columns, and the chain
+//! identifier is optional, so records are read from both ends. Some writers add columns
+//! after the radius, such as an element symbol, which are skipped.
+
+use std::{fmt, io, path::Path};
+
+use crate::{color::Diverging, pdb::Record, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Pqr {
+    pub atoms: Vec<Atom>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub record: Record,
+    pub serial: u32,
+    pub name: String,
+    pub res_name: String,
+    pub chain_id: Option<String>,
+    pub res_seq: i32,
+    pub i_code: Option<char>,
+    /// angstroms
+    pub pos: [f32; 3],
+    /// elementary charges
+    pub charge: f32,
+    /// angstroms
+    pub radius: f32,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// An atom record with fewer fields than the format requires.
+    MissingFields,
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+}
+
+impl Pqr {
+    /// Radii come from the file and colors from the charge.
+    pub fn to_atoms(&self, colors: &Diverging) -> Vec<AtomCpu> {
+        self.atoms
+            .iter()
+            .map(|a| AtomCpu {
+                pos: a.pos,
+                color: colors.color(a.charge),
+                radius: a.radius,
+            })
+            .collect()
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Pqr, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+}
+
+pub fn parse(src: &str) -> Result<Pqr, Error> {
+    let mut pqr = Pqr::default();
+    for (i, line) in src.lines().enumerate() {
+        let record = match line.split_whitespace().next() {
+            Some("ATOM") => Record::Atom,
+            Some("HETATM") => Record::Hetatm,
+            _ => continue,
+        };
+        let atom = parse_atom(line, record).map_err(|kind| Error::Parse { line: i + 1, kind })?;
+        pqr.atoms.push(atom);
+    }
+    Ok(pqr)
+}
+
+/// `ATOM serial name res_name [chain_id] res_seq x y z charge radius [extra...]`
+fn parse_atom(line: &str, record: Record) -> Result<Atom, ErrorKind> {
+    let mut fields: Vec<&str> = line.split_whitespace().collect();
+    // the radius is the last number on the line
+    while fields.len() > 9 && fields.last().is_some_and(|f| f.parse::<f32>().is_err()) {
+        fields.pop();
+    }
+    if fields.len() < 9 {
+        return Err(ErrorKind::MissingFields);
+    }
+    let (middle, numbers) = fields[4..].split_at(fields.len() - 9);
+    let (chain_id, res_seq) = match middle {
+        [res_seq] => (None, *res_seq),
+        [chain_id, res_seq] => (Some(chain_id.to_string()), *res_seq),
+        _ => return Err(ErrorKind::MissingFields),
+    };
+    let num = |s: &str, field: &'static str| {
+        s.parse::<f32>().map_err(|_| ErrorKind::InvalidField {
+            field,
+            value: s.to_string(),
+        })
+    };
+
+    // an insertion code may be appended to the residue number
+    let (number, i_code) = match res_seq.chars().last() {
+        Some(c) if c.is_ascii_alphabetic() => (&res_seq[..res_seq.len() - 1], Some(c)),
+        _ => (res_seq, None),
+    };
+
+    Ok(Atom {
+        record,
+        serial: fields[1].parse().map_err(|_| ErrorKind::InvalidField {
+            field: "serial",
+            value: fields[1].to_string(),
+        })?,
+        name: fields[2].to_string(),
+        res_name: fields[3].to_string(),
+        chain_id,
+        res_seq: number.parse().map_err(|_| ErrorKind::InvalidField {
+            field: "residue number",
+            value: res_seq.to_string(),
+        })?,
+        i_code,
+        pos: [
+            num(numbers[0], "x")?,
+            num(numbers[1], "y")?,
+            num(numbers[2], "z")?,
+        ],
+        charge: num(numbers[3], "charge")?,
+        radius: num(numbers[4], "radius")?,
+    })
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::MissingFields => write!(f, "atom record has too few fields"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atom(line: &str) -> Atom {
+        let pqr = parse(line).unwrap();
+        assert_eq!(pqr.atoms.len(), 1);
+        pqr.atoms.into_iter().next().unwrap()
+    }
+
+    fn assert_numbers(atom: &Atom) {
+        assert_eq!(atom.pos, [1.5, -2.25, 3.0]);
+        assert_eq!(atom.charge, -0.4157);
+        assert_eq!(atom.radius, 1.824);
+    }
+
+    #[test]
+    fn without_chain_id() {
+        let atom = atom("ATOM      1  N   ALA     12       1.500  -2.250   3.000 -0.4157 1.8240");
+        assert_eq!(atom.chain_id, None);
+        assert_eq!(atom.res_seq, 12);
+        assert_eq!((atom.name.as_str(), atom.res_name.as_str()), ("N", "ALA"));
+        assert_numbers(&atom);
+    }
+
+    #[test]
+    fn with_chain_id_and_insertion_code() {
+        let atom = atom("HETATM   7  O   HOH B  12A      1.500  -2.250   3.000 -0.4157 1.8240");
+        assert_eq!(atom.record, Record::Hetatm);
+        assert_eq!(atom.chain_id.as_deref(), Some("B"));
+        assert_eq!((atom.res_seq, atom.i_code), (12, Some('A')));
+        assert_numbers(&atom);
+    }
+
+    #[test]
+    fn trailing_columns_are_skipped() {
+        let with_element =
+            atom("ATOM      1  N   ALA     12       1.500  -2.250   3.000 -0.4157 1.8240 N");
+        assert_eq!(
+            (with_element.chain_id.as_deref(), with_element.res_seq),
+            (None, 12)
+        );
+        assert_numbers(&with_element);
+
+        let with_chain =
+            atom("ATOM      1  N   ALA A   12       1.500  -2.250   3.000 -0.4157 1.8240 N");
+        assert_eq!(
+            (with_chain.chain_id.as_deref(), with_chain.res_seq),
+            (Some("A"), 12)
+        );
+        assert_numbers(&with_chain);
+    }
+
+    #[test]
+    fn too_few_fields() {
+        assert!(matches!(
+            parse("ATOM      1  N   ALA     12       1.500  -2.250   3.000 -0.4157"),
+            Err(Error::Parse {
+                line: 1,
+                kind: ErrorKind::MissingFields
+            })
+        ));
+    }
+}
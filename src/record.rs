@@ -0,0 +1,135 @@
+//! Recording movies. Frames are rendered at a fixed timestep of virtual time rather than
+//! as fast as the clock allows, so every frame is exact and a recording can be repeated.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    time::Duration,
+};
+
+use crate::render::Render;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Where to write `frame_00000.png` and onwards.
+    pub png_directory: Option<PathBuf>,
+    /// A video file for `ffmpeg` to encode, in whatever format its extension names.
+    pub video: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            fps: 30,
+            png_directory: None,
+            video: None,
+        }
+    }
+}
+
+pub struct Recorder {
+    options: Options,
+    frame: u32,
+    ffmpeg: Option<(Child, ChildStdin)>,
+}
+
+impl Recorder {
+    pub fn new(options: Options) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if options.width == 0 || options.height == 0 || options.fps == 0 {
+            return Err(invalid(format!(
+                "cannot record {}x{} frames at {} fps",
+                options.width, options.height, options.fps
+            )));
+        }
+        // yuv420p stores color for each 2x2 block of pixels
+        if options.video.is_some()
+            && (!options.width.is_multiple_of(2) || !options.height.is_multiple_of(2))
+        {
+            return Err(invalid(format!(
+                "videos need an even width and height, not {}x{}",
+                options.width, options.height
+            )));
+        }
+        if let Some(directory) = &options.png_directory {
+            fs::create_dir_all(directory)?;
+        }
+        let ffmpeg = options
+            .video
+            .as_ref()
+            .map(|video| spawn_ffmpeg(video, &options))
+            .transpose()?;
+        Ok(Self {
+            options,
+            frame: 0,
+            ffmpeg,
+        })
+    }
+
+    /// The virtual time of the next frame.
+    pub fn time(&self) -> Duration {
+        Duration::from_secs(self.frame as u64) / self.options.fps
+    }
+
+    /// Advance `render` to the time of the next frame, draw it and write it out.
+    pub async fn record(&mut self, render: &mut Render) -> io::Result<()> {
+        render.update_at(self.time());
+        let image = render
+            .render_tiled(self.options.width, self.options.height)
+            .await;
+
+        if let Some(directory) = &self.options.png_directory {
+            image.save_png(directory.join(format!("frame_{:05}.png", self.frame)))?;
+        }
+        if let Some((_, stdin)) = &mut self.ffmpeg {
+            stdin.write_all(&image.rgba)?;
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    /// Wait for the video to be encoded.
+    pub fn finish(self) -> io::Result<()> {
+        let Some((mut ffmpeg, stdin)) = self.ffmpeg else {
+            return Ok(());
+        };
+        // closing its input is how ffmpeg knows the video is over
+        drop(stdin);
+        let status = ffmpeg.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg failed with {status}")));
+        }
+        Ok(())
+    }
+}
+
+/// Start `ffmpeg` reading raw RGBA frames from its standard input.
+fn spawn_ffmpeg(video: &Path, options: &Options) -> io::Result<(Child, ChildStdin)> {
+    let mut child = Command::new("ffmpeg")
+        .args([
+            "-y",
+            "-loglevel",
+            "error",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+        ])
+        .args(["-s", &format!("{}x{}", options.width, options.height)])
+        .args(["-r", &options.fps.to_string(), "-i", "-"])
+        // the most widely playable choice for H.264
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(video)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to start ffmpeg: {e}")))?;
+    let stdin = child.stdin.take().expect("stdin was piped");
+    Ok((child, stdin))
+}
@@ -1,25 +1,95 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    f32::consts::TAU,
+    num::NonZeroU32,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use glam::{vec3, Mat4};
 use winit::{dpi::PhysicalSize, window::Window};
 
-use crate::atom_renderer::AtomRenderer;
+use crate::{
+    atom_renderer::AtomRenderer,
+    bond_renderer::BondRenderer,
+    camera::{Bounds, Camera},
+    contour_renderer::ContourRenderer,
+    image::Image,
+    isosurface_renderer::IsosurfaceRenderer,
+};
 
 pub struct Render {
     atom_renderer: AtomRenderer,
+    bond_renderer: BondRenderer,
+    isosurface_renderer: IsosurfaceRenderer,
+    contour_renderer: ContourRenderer,
     device: Arc<wgpu::Device>,
 
-    surface: wgpu::Surface,
-    _window: Arc<Window>, // window must outlive surface for safety
+    target: Target,
+    size: PhysicalSize<u32>,
 
     depth_texture: wgpu::Texture,
     queue: Arc<wgpu::Queue>,
     swapchain_format: wgpu::TextureFormat,
+    background: wgpu::Color,
+    camera: Camera,
+    bounds: Bounds,
+    transform: Mat4,
+    spinning: bool,
 
     // should be part of a separate type as it does't have to to with rendering
     start: Instant,
 }
 
+enum Target {
+    Window {
+        surface: wgpu::Surface,
+        _window: Arc<Window>, // window must outlive surface for safety
+    },
+    /// Rendering without a window, to be read back with [`Render::read_pixels`].
+    Texture(wgpu::Texture),
+}
+
+/// Settings for [`Render::headless`].
+#[derive(Clone, Debug)]
+pub struct Headless {
+    pub width: u32,
+    pub height: u32,
+    pub background: [f64; 3],
+    /// Clear to fully transparent instead of the background color.
+    pub transparent: bool,
+    /// Use a software adapter, for machines without a GPU.
+    pub software: bool,
+}
+
+impl Default for Headless {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            background: [0.0; 3],
+            transparent: false,
+            software: false,
+        }
+    }
+}
+
+/// Colors that are not part of the atoms themselves. Lighting is fixed in the shaders,
+/// so there is nothing more to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    /// With alpha, which is zero for a transparent background.
+    pub background: [f64; 4],
+    /// For isosurfaces at positive and negative levels.
+    pub surface_colors: [[f32; 3]; 2],
+    pub contour_color: [f32; 3],
+}
+
+/// How long the view takes to turn around once.
+pub const TURN_SECONDS: f32 = 4.0 * TAU;
+
+/// Offscreen renders are 8 bit sRGB, to be written out as is.
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
 impl Render {
     pub async fn create(window: Arc<Window>) -> Self {
         let size = window.inner_size();
@@ -37,25 +107,7 @@ impl Render {
             .await
             .expect("Failed to find an appropriate adapter");
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: AtomRenderer::need_features(),
-                    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                    limits: {
-                        let mut limits = wgpu::Limits::downlevel_webgl2_defaults()
-                            .using_resolution(adapter.limits());
-                        limits.max_push_constant_size = 4;
-                        limits
-                    },
-                },
-                None,
-            )
-            .await
-            .expect("Failed to create device");
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
+        let (device, queue) = request_device(&adapter).await;
 
         let swapchain_format = surface.get_preferred_format(&adapter).unwrap();
 
@@ -70,33 +122,165 @@ impl Render {
             },
         );
 
+        let target = Target::Window {
+            surface,
+            _window: window,
+        };
+        Self::with_target(device, queue, target, swapchain_format, size)
+    }
+
+    /// A renderer that draws into an offscreen texture instead of a window. The texture
+    /// is no larger than the device allows, see [`Render::render_tiled`] for bigger images.
+    pub async fn headless(options: &Headless) -> Self {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: options.software,
+                compatible_surface: None,
+            })
+            .await
+            .expect("Failed to find an appropriate adapter");
+
+        let (device, queue) = request_device(&adapter).await;
+
+        let max = device.limits().max_texture_dimension_2d;
+        let size = PhysicalSize::new(options.width.min(max), options.height.min(max));
+        let target = Target::Texture(color_texture_with_size(size.width, size.height, &device));
+        let mut render = Self::with_target(device, queue, target, HEADLESS_FORMAT, size);
+        let [r, g, b] = options.background;
+        render.background = if options.transparent {
+            wgpu::Color::TRANSPARENT
+        } else {
+            wgpu::Color { r, g, b, a: 1.0 }
+        };
+        render
+    }
+
+    fn with_target(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        target: Target,
+        swapchain_format: wgpu::TextureFormat,
+        size: PhysicalSize<u32>,
+    ) -> Self {
         let atom_renderer =
             AtomRenderer::create(Arc::clone(&device), Arc::clone(&queue), swapchain_format);
+        let bond_renderer =
+            BondRenderer::create(Arc::clone(&device), Arc::clone(&queue), swapchain_format);
+        let isosurface_renderer =
+            IsosurfaceRenderer::create(Arc::clone(&device), Arc::clone(&queue), swapchain_format);
+        let contour_renderer =
+            ContourRenderer::create(Arc::clone(&device), Arc::clone(&queue), swapchain_format);
 
-        Self {
+        let mut render = Self {
             depth_texture: depth_buffer_with_size(size.width, size.height, &device),
             atom_renderer,
+            bond_renderer,
+            isosurface_renderer,
+            contour_renderer,
             device,
-            _window: window,
-            surface,
+            target,
+            size,
             queue,
             swapchain_format,
+            background: wgpu::Color::BLACK,
+            camera: Camera {
+                aspect: size.width as f32 / size.height as f32,
+                ..Camera::default()
+            },
+            bounds: Bounds::default(),
+            transform: Mat4::IDENTITY,
+            spinning: true,
             start: Instant::now(),
-        }
+        };
+        render.set_camera();
+        render
     }
 
     pub fn update(&mut self) {
-        self.atom_renderer.set_transform(Mat4::from_axis_angle(
+        self.update_at(Instant::now().duration_since(self.start));
+    }
+
+    /// Like [`Render::update`], but as it would be `time` after starting rather than now.
+    pub fn update_at(&mut self, time: Duration) {
+        if !self.spinning {
+            return;
+        }
+        self.set_transform(Mat4::from_axis_angle(
             vec3(0.0, 1.0, 0.0),
-            (Instant::now().duration_since(self.start).as_secs_f32() / 4.0) % core::f32::consts::TAU,
+            TAU * time.as_secs_f32() / TURN_SECONDS % TAU,
         ));
     }
 
+    /// Whether [`Render::update`] turns the view. Stop it to keep a view that was set.
+    pub fn set_spinning(&mut self, spinning: bool) {
+        self.spinning = spinning;
+    }
+
+    pub fn spinning(&self) -> bool {
+        self.spinning
+    }
+
+    pub fn style(&self) -> Style {
+        let wgpu::Color { r, g, b, a } = self.background;
+        let (positive, negative) = self.isosurface_renderer.colors();
+        Style {
+            background: [r, g, b, a],
+            surface_colors: [positive, negative],
+            contour_color: self.contour_renderer.color(),
+        }
+    }
+
+    /// Surface colors take effect at the next `IsosurfaceRenderer::set_grid`.
+    pub fn set_style(&mut self, style: &Style) {
+        let [r, g, b, a] = style.background;
+        self.background = wgpu::Color { r, g, b, a };
+        let [positive, negative] = style.surface_colors;
+        self.isosurface_renderer.set_colors(positive, negative);
+        self.contour_renderer.set_color(style.contour_color);
+    }
+
+    /// Set the view for everything drawn, as a turn of the scene about its middle.
+    /// [`Render::update`] overrides this while the view is spinning.
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.camera.frame(&self.bounds, transform);
+        self.transform = transform;
+        self.set_camera();
+    }
+
+    /// Keep `bounds` in view, however large it is.
+    pub fn set_bounds(&mut self, bounds: Bounds) {
+        self.bounds = bounds;
+        self.set_transform(self.transform);
+    }
+
+    /// Upload the camera after it changes.
+    fn set_camera(&mut self) {
+        self.atom_renderer.set_camera(&self.camera);
+        self.bond_renderer.set_camera(&self.camera);
+        self.isosurface_renderer.set_camera(&self.camera);
+        self.contour_renderer.set_camera(&self.camera);
+    }
+
     pub fn frame(&self) {
-        let frame = self.surface.get_current_texture().unwrap();
-        let view = frame
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        match &self.target {
+            Target::Window { surface, .. } => {
+                let frame = surface.get_current_texture().unwrap();
+                let view = frame
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                self.draw(&view);
+                frame.present();
+            }
+            Target::Texture(texture) => {
+                self.draw(&texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            }
+        }
+    }
+
+    fn draw(&self, view: &wgpu::TextureView) {
         let depth_texture_view = self
             .depth_texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -108,10 +292,10 @@ impl Render {
                 encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: None,
                     color_attachments: &[wgpu::RenderPassColorAttachment {
-                        view: &view,
+                        view,
                         resolve_target: None,
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            load: wgpu::LoadOp::Clear(self.background),
                             store: true,
                         },
                     }],
@@ -126,33 +310,220 @@ impl Render {
                 });
 
             self.atom_renderer.render(&mut pass);
+            self.bond_renderer.render(&mut pass);
+            self.isosurface_renderer.render(&mut pass);
+            self.contour_renderer.render(&mut pass);
         }
 
         self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Copy the last rendered frame back from the GPU. Only for [`Render::headless`]
+    /// renderers, window surfaces cannot be read.
+    pub async fn read_pixels(&self) -> Image {
+        let Target::Texture(texture) = &self.target else {
+            panic!("only headless renders can be read back");
+        };
+        let PhysicalSize { width, height } = self.size;
+
+        // rows in the copy are padded to a fixed alignment
+        let row_len = width as usize * 4;
+        let padded_row_len = row_len.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_row_len * height as usize) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_len as u32),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        mapping.await.expect("Failed to read back the frame");
+
+        let mut image = Image::new(width, height);
+        let data = slice.get_mapped_range();
+        for (row, padded) in image
+            .rgba
+            .chunks_mut(row_len)
+            .zip(data.chunks(padded_row_len))
+        {
+            row.copy_from_slice(&padded[..row_len]);
+        }
+        image
+    }
+
+    /// Render a `width` by `height` image, which may be larger than a texture can be, as
+    /// tiles the size of the headless texture stitched together. Each tile sees its part
+    /// of the whole image, so atoms keep their size and depth across the seams.
+    pub async fn render_tiled(&mut self, width: u32, height: u32) -> Image {
+        let PhysicalSize {
+            width: tile_width,
+            height: tile_height,
+        } = self.size;
+        // the camera frames the whole image, and each tile is cut from that
+        let aspect = self.camera.aspect;
+        self.camera.aspect = width as f32 / height as f32;
+        self.set_camera();
 
-        frame.present();
+        let mut image = Image::new(width, height);
+        for y in (0..height).step_by(tile_height as usize) {
+            for x in (0..width).step_by(tile_width as usize) {
+                self.set_tile(tile_projection([x, y], self.size, [width, height]));
+                self.frame();
+                let tile = self.read_pixels().await;
+
+                // tiles along the right and bottom edges hang over the image
+                let columns = tile_width.min(width - x) as usize;
+                let rows = tile_height.min(height - y) as usize;
+                for row in 0..rows {
+                    let from = row * tile_width as usize * 4;
+                    let to = ((y as usize + row) * width as usize + x as usize) * 4;
+                    image.rgba[to..to + columns * 4]
+                        .copy_from_slice(&tile.rgba[from..from + columns * 4]);
+                }
+            }
+        }
+        self.set_tile(Mat4::IDENTITY);
+        self.camera.aspect = aspect;
+        self.set_camera();
+        image
+    }
+
+    fn set_tile(&mut self, tile: Mat4) {
+        self.atom_renderer.set_tile(tile);
+        self.bond_renderer.set_tile(tile);
+        self.isosurface_renderer.set_tile(tile);
+        self.contour_renderer.set_tile(tile);
     }
 
     pub fn resize(&mut self, PhysicalSize { width, height }: winit::dpi::PhysicalSize<u32>) {
         debug_assert!(width != 0 && height != 0);
         if width != 0 && height != 0 {
-            self.surface.configure(
-                &self.device,
-                &wgpu::SurfaceConfiguration {
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    format: self.swapchain_format,
-                    width,
-                    height,
-                    present_mode: wgpu::PresentMode::Fifo,
-                },
-            );
+            match &mut self.target {
+                Target::Window { surface, .. } => surface.configure(
+                    &self.device,
+                    &wgpu::SurfaceConfiguration {
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        format: self.swapchain_format,
+                        width,
+                        height,
+                        present_mode: wgpu::PresentMode::Fifo,
+                    },
+                ),
+                Target::Texture(texture) => {
+                    *texture = color_texture_with_size(width, height, &self.device)
+                }
+            }
+            self.size = PhysicalSize::new(width, height);
             self.depth_texture = depth_buffer_with_size(width, height, &self.device);
+            // so that atoms stay round in a window of any shape
+            self.camera.aspect = width as f32 / height as f32;
+            self.set_camera();
         }
     }
 
+    /// The transform from the last [`Render::update`], for exporting the view.
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
     pub fn atom_renderer_mut(&mut self) -> &mut AtomRenderer {
         &mut self.atom_renderer
     }
+
+    pub fn bond_renderer_mut(&mut self) -> &mut BondRenderer {
+        &mut self.bond_renderer
+    }
+
+    pub fn isosurface_renderer_mut(&mut self) -> &mut IsosurfaceRenderer {
+        &mut self.isosurface_renderer
+    }
+
+    pub fn contour_renderer_mut(&mut self) -> &mut ContourRenderer {
+        &mut self.contour_renderer
+    }
+}
+
+/// Maps clip space of the whole `image` onto clip space of the tile whose top left
+/// pixel is `corner`. Only x and y change, so depth is the same in every tile.
+fn tile_projection(corner: [u32; 2], tile: PhysicalSize<u32>, image: [u32; 2]) -> Mat4 {
+    let scale = vec3(
+        image[0] as f32 / tile.width as f32,
+        image[1] as f32 / tile.height as f32,
+        1.0,
+    );
+    // clip space y points up while pixel rows go down
+    let offset = vec3(
+        scale.x - 1.0 - 2.0 * corner[0] as f32 / tile.width as f32,
+        1.0 - scale.y + 2.0 * corner[1] as f32 / tile.height as f32,
+        0.0,
+    );
+    Mat4::from_translation(offset) * Mat4::from_scale(scale)
+}
+
+async fn request_device(adapter: &wgpu::Adapter) -> (Arc<wgpu::Device>, Arc<wgpu::Queue>) {
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: AtomRenderer::need_features()
+                    | BondRenderer::need_features()
+                    | IsosurfaceRenderer::need_features()
+                    | ContourRenderer::need_features(),
+                // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+                limits: {
+                    let mut limits = wgpu::Limits::downlevel_webgl2_defaults()
+                        .using_resolution(adapter.limits());
+                    limits.max_push_constant_size = 4;
+                    limits
+                },
+            },
+            None,
+        )
+        .await
+        .expect("Failed to create device");
+    (Arc::new(device), Arc::new(queue))
+}
+
+fn color_texture_with_size(w: u32, h: u32, device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: w,
+            height: h,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HEADLESS_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        label: None,
+    })
 }
 
 fn depth_buffer_with_size(w: u32, h: u32, device: &wgpu::Device) -> wgpu::Texture {
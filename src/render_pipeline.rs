@@ -22,6 +22,20 @@ struct AtomGpu {
 pub type UniformCpu = glam::Mat4;
 type UniformGpu = float4x4;
 
+/// See [`crate::camera::Camera::uniform`].
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct CameraCpu {
+    pub view: glam::Mat4,
+    pub projection: glam::Mat4,
+}
+
+#[derive(shame::Fields)]
+pub struct CameraGpu {
+    pub view: float4x4,
+    pub projection: float4x4,
+}
+
 pub fn features_used() -> wgpu::Features {
     wgpu::Features::PUSH_CONSTANTS | wgpu::Features::DEPTH_CLIP_CONTROL
 }
@@ -31,47 +45,76 @@ pub fn pipeline(mut f: RenderFeatures) {
 
     let vertex: VertexGpu = f.io.vertex_buffer();
     let atom: AtomGpu = f.io.instance_buffer();
-    let transform: UniformGpu = f.io.group().uniform_block();
-
-    let pos = transform * (atom.pos, 1.0);
-
-    let clip_position = pos + (vertex.xy() * atom.radius, 0.0, 0.0);
+    // view space has the camera at the origin looking down -z
+    let camera: CameraGpu = f.io.group().uniform_block();
+    // picks out part of the image when rendering in tiles, see `Render::render_tiled`.
+    // It comes after projection so that atoms are the same size in every tile.
+    let tile: UniformGpu = f.io.group().uniform_block();
+
+    let center = (camera.view * (atom.pos, 1.0)).xyz();
+
+    // The outline of the sphere is where the cone of rays from the camera touches it, a
+    // circle facing the camera. The square around that circle is the smallest quad that
+    // covers the sphere on screen.
+    let toward = center.normalize();
+    let shrink = 1.0 - atom.radius * atom.radius / center.dot(center);
+    let outline_center = center * shrink;
+    let outline_radius = atom.radius * shrink.sqrt();
+    let side = (toward.z(), 0.0, 0.0 - toward.x()).rec().normalize();
+    let up = (
+        0.0 - toward.x() * toward.y(),
+        toward.x() * toward.x() + toward.z() * toward.z(),
+        0.0 - toward.y() * toward.z(),
+    )
+        .rec()
+        .normalize();
+    let corner = outline_center + (side * vertex.x() + up * vertex.y()) * outline_radius;
+
+    let clip_position = tile * (camera.projection * (corner, 1.0));
     let poly = f.raster.rasterize(clip_position, Cull::Off, index);
 
-    let uv = poly.lerp(vertex.xy());
+    // the ray from the camera through this fragment, which is exact under perspective
+    // since the corners are interpolated in view space
+    let ray = poly.lerp(corner).normalize();
+    let center = poly.lerp(center);
+    let radius = poly.lerp(atom.radius);
 
-    let distance_to_center_squared = uv.dot(uv);
-    distance_to_center_squared
-        .gt(&1.0)
+    let along = ray.dot(center);
+    let discriminant = along * along - center.dot(center) + radius * radius;
+    (0.0 - discriminant)
+        .gt(&0.0)
         .then(|| Any::discard_fragment());
 
-    let dr = (1.0 - distance_to_center_squared).sqrt();
-    let hit_normal = (uv, dr).rec().normalize();
-
-    // the direction to the light source
-    // not the direction light is traveling
-    let light_direction = (1.0, -2.0, 3.0, 0.0);
-    let light_direction = transform * light_direction;
+    // the nearer of the two hits
+    let hit = ray * (along - discriminant.sqrt());
+    let hit_normal = (hit - center).normalize();
 
-    let light_direction = transform * light_direction;
-    let lighta_intensity = 0.2 + light_direction.xyz().normalize().dot(hit_normal).max(0.0) * 2.0;
-    let lighta_color = (0.2, 0.1, 0.3);
-    let lighta = lighta_intensity * lighta_color;
-
-    let light_direction = transform * light_direction;
-    let lightb_intensity = 0.2 + light_direction.xyz().normalize().dot(hit_normal).max(0.0) * 2.0;
-    let lightb_color = (0.2, 0.3, 0.1);
-    let lightb = lightb_intensity * lightb_color;
+    let light = lighting(light_direction(camera.view).dot(hit_normal));
 
-    let radius = poly.lerp(atom.radius);
-    let base_distance = poly.lerp(clip_position.rec().z());
-
-    let depth = dr * radius + base_distance;
+    // the depth the hit would have if it were a triangle, so that spheres cut into each
+    // other and into everything else where they meet
+    let hit_clip = camera.projection * (hit, 1.0);
+    let depth = hit_clip.z() / hit_clip.w();
 
     f.io.depth::<Depth32>()
         .test_write(DepthTest::Greater, DepthWrite::Write(depth));
 
-    let color = poly.lerp(atom.color);
-    let color = color * lighta + color * lightb;
-    f.io.color::<RGBA_Surface>().set((color, 0.0));
+    let color = poly.lerp(atom.color) * light;
+    f.io.color::<RGBA_Surface>().set((color, 1.0));
+}
+
+/// The direction to the light source in view space, not the direction light is traveling.
+/// The light is fixed to the scene, so `view` turns it along with everything else.
+pub fn light_direction(view: float4x4) -> float3 {
+    (view * (1.0, -2.0, 3.0, 0.0)).xyz().normalize()
+}
+
+/// How much light falls on a surface whose normal makes an angle with the cosine `facing`
+/// to the [`light_direction`]. Atoms, bonds and surfaces are all lit this way so they look
+/// like part of one scene.
+pub fn lighting(facing: float) -> float3 {
+    let intensity = 0.2 + facing.max(0.0) * 2.0;
+    let lighta_color = (0.2, 0.1, 0.3);
+    let lightb_color = (0.2, 0.3, 0.1);
+    intensity * lighta_color + intensity * lightb_color
 }
@@ -0,0 +1,545 @@
+//! Reader for MDL molfiles (V2000 and V3000) and SD files, which are molfiles
+//! followed by data items and separated by `$$$$`.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Molecule {
+    pub name: String,
+    /// Second header line: user, program, date and dimensionality.
+    pub program: String,
+    pub comment: String,
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<Bond>,
+    /// SD data items in order of appearance. Multi-line values are joined with `\n`.
+    pub data: Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub element: String,
+    /// angstroms
+    pub pos: [f32; 3],
+    pub charge: i8,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bond {
+    /// zero based atom indices
+    pub atoms: [usize; 2],
+    pub order: BondOrder,
+    pub stereo: Stereo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BondOrder {
+    Single,
+    Double,
+    Triple,
+    Aromatic,
+    SingleOrDouble,
+    SingleOrAromatic,
+    DoubleOrAromatic,
+    Any,
+    /// Coordination, hydrogen and other V3000-only bond types.
+    Other(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stereo {
+    None,
+    /// wedge
+    Up,
+    /// hash
+    Down,
+    /// unknown configuration, a wavy single bond or a crossed double bond
+    Either,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The input ended in the middle of a record.
+    Truncated,
+    UnsupportedVersion(String),
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    /// A bond refers to an atom index that does not exist.
+    NoSuchAtom(usize),
+    /// A V3000 line that does not fit the expected block structure.
+    UnexpectedLine(String),
+}
+
+impl Molecule {
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms.iter().map(Atom::to_atom_cpu).collect()
+    }
+
+    pub fn data(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl Atom {
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = element::lookup_or_unknown(&self.element);
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+impl BondOrder {
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => BondOrder::Single,
+            2 => BondOrder::Double,
+            3 => BondOrder::Triple,
+            4 => BondOrder::Aromatic,
+            5 => BondOrder::SingleOrDouble,
+            6 => BondOrder::SingleOrAromatic,
+            7 => BondOrder::DoubleOrAromatic,
+            8 => BondOrder::Any,
+            other => BondOrder::Other(other),
+        }
+    }
+}
+
+/// Streams molecules out of a molfile or SD file one record at a time.
+pub struct Reader<R> {
+    inner: R,
+    line: usize,
+    buf: String,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(
+            File::open(path).map_err(Error::Io)?,
+        )))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Returns false at end of input.
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.buf.clear();
+        let read = self.inner.read_line(&mut self.buf).map_err(Error::Io)?;
+        self.line += 1;
+        let trimmed = self.buf.trim_end_matches(['\n', '\r']).len();
+        self.buf.truncate(trimmed);
+        Ok(read != 0)
+    }
+
+    /// Like `next_line` but end of input is an error.
+    fn require_line(&mut self) -> Result<&str, Error> {
+        if self.next_line()? {
+            Ok(&self.buf)
+        } else {
+            Err(self.error(ErrorKind::Truncated))
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn read_molecule(&mut self) -> Result<Option<Molecule>, Error> {
+        if !self.next_line()? {
+            return Ok(None);
+        }
+        let name = self.buf.clone();
+        // trailing blank lines after the last record
+        if !self.next_line()? && name.trim().is_empty() {
+            return Ok(None);
+        }
+        let program = self.buf.clone();
+        let comment = self.require_line()?.to_string();
+        let mut molecule = Molecule {
+            name,
+            program,
+            comment,
+            ..Default::default()
+        };
+
+        let counts = Fixed(self.require_line()?);
+        let version = counts.opt(33..39).unwrap_or("V2000").to_string();
+        let sizes = counts
+            .num(0..3, "atom count")
+            .and_then(|atoms| Ok((atoms, counts.num(3..6, "bond count")?)));
+        match version.as_str() {
+            "V2000" => {
+                let (atoms, bonds) = sizes.map_err(|k| self.error(k))?;
+                self.read_v2000(&mut molecule, atoms, bonds)?;
+            }
+            "V3000" => self.read_v3000(&mut molecule)?,
+            _ => return Err(self.error(ErrorKind::UnsupportedVersion(version))),
+        }
+
+        self.read_data_items(&mut molecule)?;
+        Ok(Some(molecule))
+    }
+
+    fn read_v2000(
+        &mut self,
+        molecule: &mut Molecule,
+        atoms: usize,
+        bonds: usize,
+    ) -> Result<(), Error> {
+        for _ in 0..atoms {
+            let line = Fixed(self.require_line()?);
+            let atom = parse_v2000_atom(&line).map_err(|k| self.error(k))?;
+            molecule.atoms.push(atom);
+        }
+        for _ in 0..bonds {
+            let line = Fixed(self.require_line()?);
+            let bond = parse_v2000_bond(&line, atoms).map_err(|k| self.error(k))?;
+            molecule.bonds.push(bond);
+        }
+
+        // properties block; charges given here supersede the atom block
+        let mut charges_reset = false;
+        loop {
+            let line = self.require_line()?;
+            if line.starts_with("M  END") {
+                return Ok(());
+            }
+            if let Some(rest) = line.strip_prefix("M  CHG") {
+                let pairs = parse_chg(rest).map_err(|k| self.error(k))?;
+                if !charges_reset {
+                    molecule.atoms.iter_mut().for_each(|a| a.charge = 0);
+                    charges_reset = true;
+                }
+                for (index, charge) in pairs {
+                    let atom = index
+                        .checked_sub(1)
+                        .and_then(|i| molecule.atoms.get_mut(i))
+                        .ok_or_else(|| self.error(ErrorKind::NoSuchAtom(index)))?;
+                    atom.charge = charge;
+                }
+            }
+        }
+    }
+
+    /// Reads one logical V3000 line, joining `-` continuations and stripping `M  V30 `.
+    fn v30_line(&mut self) -> Result<String, Error> {
+        let mut joined = String::new();
+        loop {
+            let line = self.require_line()?;
+            let content = match line.strip_prefix("M  V30 ") {
+                Some(content) => content,
+                None => {
+                    let line = line.to_string();
+                    return Err(self.error(ErrorKind::UnexpectedLine(line)));
+                }
+            };
+            match content.strip_suffix('-') {
+                Some(partial) => joined.push_str(partial),
+                None => {
+                    joined.push_str(content);
+                    return Ok(joined);
+                }
+            }
+        }
+    }
+
+    fn read_v3000(&mut self, molecule: &mut Molecule) -> Result<(), Error> {
+        let unexpected = |line: &str| ErrorKind::UnexpectedLine(line.to_string());
+        loop {
+            let line = self.v30_line()?;
+            let line = line.trim();
+            match line {
+                "BEGIN ATOM" => loop {
+                    let line = self.v30_line()?;
+                    if line.trim() == "END ATOM" {
+                        break;
+                    }
+                    let atom = parse_v3000_atom(&line).map_err(|k| self.error(k))?;
+                    molecule.atoms.push(atom);
+                },
+                "BEGIN BOND" => loop {
+                    let line = self.v30_line()?;
+                    if line.trim() == "END BOND" {
+                        break;
+                    }
+                    let bond =
+                        parse_v3000_bond(&line, molecule.atoms.len()).map_err(|k| self.error(k))?;
+                    molecule.bonds.push(bond);
+                },
+                "END CTAB" => break,
+                _ if line.starts_with("BEGIN CTAB") || line.starts_with("COUNTS") => {}
+                _ if line.starts_with("BEGIN ") => {
+                    // collections, sgroups and other blocks are not interpreted
+                    let end = format!("END {}", &line["BEGIN ".len()..]);
+                    while self.v30_line()?.trim() != end {}
+                }
+                _ => return Err(self.error(unexpected(line))),
+            }
+        }
+        loop {
+            if self.require_line()?.starts_with("M  END") {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Data items follow `M  END` until `$$$$` or the end of input:
+    ///
+    /// ```text
+    /// > <NAME>
+    /// value
+    ///
+    /// ```
+    fn read_data_items(&mut self, molecule: &mut Molecule) -> Result<(), Error> {
+        let mut current: Option<(String, Vec<String>)> = None;
+        while self.next_line()? {
+            if self.buf.starts_with("$$$$") {
+                break;
+            }
+            if let Some(header) = self.buf.strip_prefix('>') {
+                if let Some((name, lines)) = current.take() {
+                    molecule.data.push((name, lines.join("\n")));
+                }
+                let name = match (header.find('<'), header.rfind('>')) {
+                    (Some(open), Some(close)) if open < close => &header[open + 1..close],
+                    _ => header.trim(),
+                };
+                current = Some((name.to_string(), vec![]));
+            } else if self.buf.is_empty() {
+                if let Some((name, lines)) = current.take() {
+                    molecule.data.push((name, lines.join("\n")));
+                }
+            } else if let Some((_, lines)) = &mut current {
+                lines.push(self.buf.clone());
+            }
+        }
+        if let Some((name, lines)) = current {
+            molecule.data.push((name, lines.join("\n")));
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Molecule, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_molecule().transpose()
+    }
+}
+
+pub fn parse(src: &str) -> Result<Vec<Molecule>, Error> {
+    Reader::new(src.as_bytes()).collect()
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Molecule>, Error> {
+    Reader::open(path)?.collect()
+}
+
+fn parse_v2000_atom(line: &Fixed) -> Result<Atom, ErrorKind> {
+    // 0 means uncharged, 4 a doublet radical
+    let charge = match line.opt(36..39) {
+        None => 0,
+        Some(c) => match c.parse::<i8>() {
+            Ok(c @ 1..=3) => 4 - c,
+            Ok(c @ 5..=7) => 4 - c,
+            Ok(0 | 4) => 0,
+            _ => return Err(invalid("charge", c)),
+        },
+    };
+    Ok(Atom {
+        pos: [
+            line.num(0..10, "x")?,
+            line.num(10..20, "y")?,
+            line.num(20..30, "z")?,
+        ],
+        element: line.opt(31..34).unwrap_or_default().to_string(),
+        charge,
+    })
+}
+
+fn parse_v2000_bond(line: &Fixed, atom_count: usize) -> Result<Bond, ErrorKind> {
+    let first = atom_index(line.num(0..3, "first atom")?, atom_count)?;
+    let second = atom_index(line.num(3..6, "second atom")?, atom_count)?;
+    let order = BondOrder::from_code(line.num(6..9, "bond type")?);
+    let stereo = match line.opt(9..12) {
+        None => Stereo::None,
+        Some(s) => match s.parse::<u8>() {
+            Ok(0) => Stereo::None,
+            Ok(1) => Stereo::Up,
+            Ok(6) => Stereo::Down,
+            Ok(3 | 4) => Stereo::Either,
+            _ => return Err(invalid("bond stereo", s)),
+        },
+    };
+    Ok(Bond {
+        atoms: [first, second],
+        order,
+        stereo,
+    })
+}
+
+/// `M  CHGnn8 aaa vvv ...`, with `rest` starting after `CHG`
+fn parse_chg(rest: &str) -> Result<Vec<(usize, i8)>, ErrorKind> {
+    let mut fields = rest.split_whitespace();
+    let count: usize = field(fields.next(), "charge count")?;
+    (0..count)
+        .map(|_| {
+            let atom = field(fields.next(), "charged atom")?;
+            let charge = field(fields.next(), "charge")?;
+            Ok((atom, charge))
+        })
+        .collect()
+}
+
+/// `index type x y z aamap [KEY=value ...]`
+fn parse_v3000_atom(line: &str) -> Result<Atom, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    let _index: usize = field(fields.next(), "atom index")?;
+    let element = fields
+        .next()
+        .ok_or(ErrorKind::Truncated)?
+        .trim_matches('"')
+        .to_string();
+    let pos = [
+        field(fields.next(), "x")?,
+        field(fields.next(), "y")?,
+        field(fields.next(), "z")?,
+    ];
+    let _aamap: usize = field(fields.next(), "atom map")?;
+    let mut charge = 0;
+    for (key, value) in fields.filter_map(|f| f.split_once('=')) {
+        if key == "CHG" {
+            charge = value.parse().map_err(|_| invalid("CHG", value))?;
+        }
+    }
+    Ok(Atom {
+        element,
+        pos,
+        charge,
+    })
+}
+
+/// `index type atom1 atom2 [KEY=value ...]`
+fn parse_v3000_bond(line: &str, atom_count: usize) -> Result<Bond, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    let _index: usize = field(fields.next(), "bond index")?;
+    let order = BondOrder::from_code(field(fields.next(), "bond type")?);
+    let first = atom_index(field(fields.next(), "first atom")?, atom_count)?;
+    let second = atom_index(field(fields.next(), "second atom")?, atom_count)?;
+    let mut stereo = Stereo::None;
+    for (key, value) in fields.filter_map(|f| f.split_once('=')) {
+        if key == "CFG" {
+            stereo = match value {
+                "0" => Stereo::None,
+                "1" => Stereo::Up,
+                "2" => Stereo::Either,
+                "3" => Stereo::Down,
+                _ => return Err(invalid("CFG", value)),
+            };
+        }
+    }
+    Ok(Bond {
+        atoms: [first, second],
+        order,
+        stereo,
+    })
+}
+
+/// Convert a one based atom number to an index.
+fn atom_index(number: usize, atom_count: usize) -> Result<usize, ErrorKind> {
+    if (1..=atom_count).contains(&number) {
+        Ok(number - 1)
+    } else {
+        Err(ErrorKind::NoSuchAtom(number))
+    }
+}
+
+fn field<T: std::str::FromStr>(value: Option<&str>, name: &'static str) -> Result<T, ErrorKind> {
+    let value = value.ok_or(ErrorKind::Truncated)?;
+    value.parse().map_err(|_| invalid(name, value))
+}
+
+fn invalid(field: &'static str, value: &str) -> ErrorKind {
+    ErrorKind::InvalidField {
+        field,
+        value: value.to_string(),
+    }
+}
+
+/// A fixed-column line, indexed by zero based column.
+struct Fixed<'a>(&'a str);
+
+impl<'a> Fixed<'a> {
+    /// The trimmed field, or None if it is blank or past the end of the line.
+    fn opt(&self, range: Range<usize>) -> Option<&'a str> {
+        let end = range.end.min(self.0.len());
+        let s = self.0.get(range.start..end)?.trim();
+        (!s.is_empty()).then_some(s)
+    }
+
+    fn num<T: std::str::FromStr>(
+        &self,
+        range: Range<usize>,
+        field: &'static str,
+    ) -> Result<T, ErrorKind> {
+        let s = self.opt(range).ok_or(ErrorKind::Truncated)?;
+        s.parse().map_err(|_| invalid(field, s))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Truncated => write!(f, "record ends early"),
+            ErrorKind::UnsupportedVersion(v) => write!(f, "unsupported molfile version {v:?}"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::NoSuchAtom(n) => write!(f, "bond refers to missing atom {n}"),
+            ErrorKind::UnexpectedLine(l) => write!(f, "unexpected line {l:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,417 @@
+//! Saved sessions: what was loaded and how it was being looked at, so a view can be
+//! picked up again later.
+//!
+//! Sessions are plain text, one setting per line:
+//!
+//! ```text
+//! bddatoms session 1
+//! source /data/1abc.pdb
+//! source /data/1abc.ccp4
+//! frame 0
+//! center_atom 12
+//! transform 1 0 0 0 0 1 0 0 0 0 1 0 0 0 0 1
+//! background 0 0 0 1
+//! surface_colors 0.1 0.3 0.9 0.9 0.2 0.1
+//! contour_color 0.3 0.5 1
+//! override 12 color 1 0 0 radius 0.05
+//! ```
+//!
+//! Numbers are written with as many digits as it takes to read them back exactly, so a
+//! restored session draws the same image.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use glam::Mat4;
+
+use crate::{render::Style, render_pipeline::AtomCpu};
+
+const HEADER: &str = "bddatoms session";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    /// The command line the scene was opened from: files, and levels where given.
+    /// Files are saved by absolute path, so the session opens from any directory.
+    pub sources: Vec<String>,
+    /// The frame or model shown.
+    pub frame: usize,
+    /// The atom a density map is contoured around, if it has been moved to one.
+    pub center_atom: Option<usize>,
+    pub overrides: Vec<Override>,
+    /// As handed to `Render::set_transform`.
+    pub transform: Mat4,
+    pub style: Style,
+}
+
+/// Changes to one atom as loaded, in the units it is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Override {
+    pub atom: usize,
+    pub color: Option<[f32; 3]>,
+    pub radius: Option<f32>,
+}
+
+impl Override {
+    pub fn apply(&self, atoms: &mut [AtomCpu]) {
+        let Some(atom) = atoms.get_mut(self.atom) else {
+            return;
+        };
+        if let Some(color) = self.color {
+            atom.color = color;
+        }
+        if let Some(radius) = self.radius {
+            atom.radius = radius;
+        }
+    }
+
+    /// The override that puts back what this one changes about `atoms`.
+    pub fn undo(&self, atoms: &[AtomCpu]) -> Override {
+        let atom = atoms.get(self.atom);
+        Override {
+            atom: self.atom,
+            color: self.color.and(atom.map(|a| a.color)),
+            radius: self.radius.and(atom.map(|a| a.radius)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The first line is not a session header.
+    NotASession,
+    UnsupportedVersion(u32),
+    UnknownSetting(String),
+    /// A setting every session has is not there.
+    MissingSetting(&'static str),
+    /// A line with fewer fields than its setting requires.
+    MissingFields,
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Session, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+}
+
+pub fn parse(src: &str) -> Result<Session, Error> {
+    let mut lines = src.lines().enumerate().map(|(n, l)| (n + 1, l));
+    let parse_error = |line, kind| Error::Parse { line, kind };
+
+    let version = lines
+        .next()
+        .and_then(|(_, l)| l.strip_prefix(HEADER))
+        .ok_or(parse_error(1, ErrorKind::NotASession))?
+        .trim();
+    let version = version.parse().map_err(|_| {
+        parse_error(
+            1,
+            ErrorKind::InvalidField {
+                field: "version",
+                value: version.to_string(),
+            },
+        )
+    })?;
+    if version != VERSION {
+        return Err(parse_error(1, ErrorKind::UnsupportedVersion(version)));
+    }
+
+    let mut sources = vec![];
+    let mut frame = None;
+    let mut center_atom = None;
+    let mut overrides = vec![];
+    let mut transform = None;
+    let mut background = None;
+    let mut surface_colors = None;
+    let mut contour_color = None;
+    for (line, text) in lines {
+        let fields = Fields {
+            line,
+            fields: text.split_whitespace().collect(),
+        };
+        let Some(&key) = fields.fields.first() else {
+            continue;
+        };
+        match key {
+            // the rest of the line, spaces and all
+            "source" => sources.push(text.trim_start()["source".len()..].trim().to_string()),
+            "frame" => frame = Some(fields.number(1, "frame")?),
+            "center_atom" => {
+                center_atom = match fields.get(1)? {
+                    "none" => None,
+                    _ => Some(fields.number(1, "center_atom")?),
+                }
+            }
+            "transform" => {
+                transform = Some(Mat4::from_cols_array(&fields.numbers(1, "transform")?))
+            }
+            "background" => background = Some(fields.numbers(1, "background")?),
+            "surface_colors" => {
+                let [a, b, c, d, e, f] = fields.numbers(1, "surface_colors")?;
+                surface_colors = Some([[a, b, c], [d, e, f]]);
+            }
+            "contour_color" => contour_color = Some(fields.numbers(1, "contour_color")?),
+            "override" => {
+                let mut o = Override {
+                    atom: fields.number(1, "atom")?,
+                    color: None,
+                    radius: None,
+                };
+                let mut i = 2;
+                while i < fields.fields.len() {
+                    match fields.fields[i] {
+                        "color" => {
+                            o.color = Some(fields.numbers(i + 1, "color")?);
+                            i += 4;
+                        }
+                        "radius" => {
+                            o.radius = Some(fields.number(i + 1, "radius")?);
+                            i += 2;
+                        }
+                        other => {
+                            let kind = ErrorKind::UnknownSetting(other.to_string());
+                            return Err(parse_error(line, kind));
+                        }
+                    }
+                }
+                overrides.push(o);
+            }
+            other => {
+                return Err(parse_error(
+                    line,
+                    ErrorKind::UnknownSetting(other.to_string()),
+                ));
+            }
+        }
+    }
+
+    // report missing settings at the end of the file, where they were looked for
+    let end = src.lines().count();
+    let missing = |setting| parse_error(end, ErrorKind::MissingSetting(setting));
+    Ok(Session {
+        sources,
+        frame: frame.ok_or_else(|| missing("frame"))?,
+        center_atom,
+        overrides,
+        transform: transform.ok_or_else(|| missing("transform"))?,
+        style: Style {
+            background: background.ok_or_else(|| missing("background"))?,
+            surface_colors: surface_colors.ok_or_else(|| missing("surface_colors"))?,
+            contour_color: contour_color.ok_or_else(|| missing("contour_color"))?,
+        },
+    })
+}
+
+pub fn save(path: impl AsRef<Path>, session: &Session) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write(&mut file, session)?;
+    file.flush()
+}
+
+pub fn write(mut w: impl Write, session: &Session) -> io::Result<()> {
+    writeln!(w, "{HEADER} {VERSION}")?;
+    for source in &session.sources {
+        writeln!(w, "source {source}")?;
+    }
+    writeln!(w, "frame {}", session.frame)?;
+    match session.center_atom {
+        Some(n) => writeln!(w, "center_atom {n}")?,
+        None => writeln!(w, "center_atom none")?,
+    }
+    writeln!(w, "transform {}", join(&session.transform.to_cols_array()))?;
+    let Style {
+        background,
+        surface_colors,
+        contour_color,
+    } = &session.style;
+    writeln!(w, "background {}", join(background))?;
+    writeln!(w, "surface_colors {}", join(surface_colors.as_flattened()))?;
+    writeln!(w, "contour_color {}", join(contour_color))?;
+    for o in &session.overrides {
+        write!(w, "override {}", o.atom)?;
+        if let Some(color) = o.color {
+            write!(w, " color {}", join(&color))?;
+        }
+        if let Some(radius) = o.radius {
+            write!(w, " radius {radius}")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Rust prints the shortest decimal that reads back as the same float.
+fn join<T: fmt::Display>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(T::to_string).collect();
+    values.join(" ")
+}
+
+struct Fields<'a> {
+    line: usize,
+    fields: Vec<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    fn get(&self, i: usize) -> Result<&'a str, Error> {
+        self.fields.get(i).copied().ok_or(Error::Parse {
+            line: self.line,
+            kind: ErrorKind::MissingFields,
+        })
+    }
+
+    fn number<T: std::str::FromStr>(&self, i: usize, field: &'static str) -> Result<T, Error> {
+        let s = self.get(i)?;
+        s.parse().map_err(|_| Error::Parse {
+            line: self.line,
+            kind: ErrorKind::InvalidField {
+                field,
+                value: s.to_string(),
+            },
+        })
+    }
+
+    /// `N` numbers starting at field `i`.
+    fn numbers<T: std::str::FromStr + Default + Copy, const N: usize>(
+        &self,
+        i: usize,
+        field: &'static str,
+    ) -> Result<[T; N], Error> {
+        let mut values = [T::default(); N];
+        for (n, v) in values.iter_mut().enumerate() {
+            *v = self.number(i + n, field)?;
+        }
+        Ok(values)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::NotASession => write!(f, "not a bddatoms session"),
+            ErrorKind::UnsupportedVersion(v) => write!(f, "unsupported session version {v}"),
+            ErrorKind::UnknownSetting(s) => write!(f, "unknown setting {s:?}"),
+            ErrorKind::MissingSetting(s) => write!(f, "missing setting {s:?}"),
+            ErrorKind::MissingFields => write!(f, "line has too few fields"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            sources: vec![
+                "/data/with space.pdb".into(),
+                "/data/x.ccp4".into(),
+                "1.5".into(),
+            ],
+            frame: 3,
+            center_atom: Some(7),
+            overrides: vec![
+                Override {
+                    atom: 2,
+                    color: Some([0.1, 0.2, 0.3]),
+                    radius: Some(1.0 / 3.0),
+                },
+                Override {
+                    atom: 4,
+                    color: None,
+                    radius: Some(1.0),
+                },
+            ],
+            transform: Mat4::from_axis_angle(Vec3::Y, 1.2345),
+            style: Style {
+                background: [0.1, 0.2, 0.3, 1.0],
+                surface_colors: [[0.1, 0.3, 0.9], [0.9, 0.2, 0.1]],
+                contour_color: [0.3, 0.5, 1.0],
+            },
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut text = vec![];
+        write(&mut text, &session()).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(parse(&text).unwrap(), session());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |src| match parse(src) {
+            Err(Error::Parse { line, kind }) => (line, kind),
+            other => panic!("expected a parse error, got {other:?}"),
+        };
+        assert!(matches!(error("nope"), (1, ErrorKind::NotASession)));
+        assert!(matches!(
+            error("bddatoms session 2\n"),
+            (1, ErrorKind::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            error("bddatoms session 1\nframe x\n"),
+            (2, ErrorKind::InvalidField { field: "frame", .. })
+        ));
+        assert!(matches!(
+            error("bddatoms session 1\nframe 1\n"),
+            (2, ErrorKind::MissingSetting("transform"))
+        ));
+        assert!(matches!(
+            error("bddatoms session 1\noverride 1 shape round\n"),
+            (2, ErrorKind::UnknownSetting(_))
+        ));
+    }
+
+    #[test]
+    fn undo_puts_back_what_was_overridden() {
+        let atom = |color| AtomCpu {
+            pos: [0.0; 3],
+            color,
+            radius: 1.5,
+        };
+        let mut atoms = vec![atom([0.2; 3]), atom([0.4; 3])];
+        let highlight = Override {
+            atom: 1,
+            color: Some([1.0, 0.0, 0.0]),
+            radius: None,
+        };
+        let undo = highlight.undo(&atoms);
+        assert_eq!(undo.color, Some([0.4; 3]));
+        assert_eq!(undo.radius, None);
+
+        highlight.apply(&mut atoms);
+        assert_eq!(atoms[1].color, [1.0, 0.0, 0.0]);
+        undo.apply(&mut atoms);
+        assert_eq!(atoms[1].color, [0.4; 3]);
+        assert_eq!(atoms[0].color, [0.2; 3]);
+    }
+}
@@ -0,0 +1,464 @@
+//! Reader for small-molecule crystal structures in CIF, as distributed by the CSD and
+//! COD.
+//!
+//! These files list only the asymmetric unit in fractional coordinates. The full unit
+//! cell is produced by applying the symmetry operators and merging the atoms that land
+//! on top of each other, which happens for sites on special positions.
+
+use std::{path::Path, str::FromStr};
+
+use crate::{
+    cif::{Error, ErrorKind, Token, Tokenizer, Value},
+    element,
+    render_pipeline::AtomCpu,
+};
+
+/// Distance in angstroms below which symmetry copies of a site are considered the same
+/// atom. Coordinates are usually given to four decimal places, so copies of a site on a
+/// special position can be a few hundredths of an angstrom apart.
+pub const DEFAULT_TOLERANCE: f32 = 0.1;
+
+/// One data block.
+#[derive(Clone, Debug, Default)]
+pub struct Crystal {
+    pub name: String,
+    pub cell: Cell,
+    /// Empty when the file does not list any, which means only the identity.
+    pub symmetry: Vec<SymOp>,
+    /// The asymmetric unit.
+    pub sites: Vec<Site>,
+}
+
+/// Cell parameters. Lengths in angstroms, angles in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub alpha: f32,
+    pub beta: f32,
+    pub gamma: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Site {
+    pub label: String,
+    /// `_atom_site_type_symbol`, which may carry an oxidation state such as `Fe3+`.
+    pub type_symbol: Option<String>,
+    pub fract: [f32; 3],
+    pub occupancy: f32,
+}
+
+/// A symmetry operator such as `-x+1/2, y, -z`, acting on fractional coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SymOp {
+    /// Rows give the new x, y and z in terms of the old ones.
+    pub rotation: [[f32; 3]; 3],
+    pub translation: [f32; 3],
+}
+
+impl Crystal {
+    /// All atoms in the unit cell, in fractional coordinates wrapped into `0..1`.
+    /// Symmetry copies of a site closer than `tolerance` angstroms to an earlier copy
+    /// are dropped.
+    pub fn unit_cell(&self, tolerance: f32) -> Vec<Site> {
+        let identity = [SymOp::IDENTITY];
+        let ops = if self.symmetry.is_empty() {
+            &identity[..]
+        } else {
+            &self.symmetry[..]
+        };
+
+        let mut atoms: Vec<Site> = vec![];
+        for site in &self.sites {
+            let first = atoms.len();
+            for op in ops {
+                let fract = op.apply(site.fract).map(|v| v - v.floor());
+                let duplicate = atoms[first..]
+                    .iter()
+                    .any(|a| self.cell.distance(a.fract, fract) < tolerance);
+                if !duplicate {
+                    atoms.push(Site {
+                        fract,
+                        ..site.clone()
+                    });
+                }
+            }
+        }
+        atoms
+    }
+
+    pub fn to_atoms(&self, tolerance: f32) -> Vec<AtomCpu> {
+        self.unit_cell(tolerance)
+            .iter()
+            .map(|site| {
+                let element = site.element();
+                AtomCpu {
+                    pos: self.cell.fractional_to_cartesian(site.fract),
+                    color: element.color,
+                    radius: element.radius,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Cell {
+    /// Cell vectors in angstroms, one per row, in the usual orientation: `a` along x and
+    /// `b` in the xy plane.
+    pub fn vectors(&self) -> [[f32; 3]; 3] {
+        let [alpha, beta, gamma] =
+            [self.alpha, self.beta, self.gamma].map(|d| (d as f64).to_radians());
+        let (a, b, c) = (self.a as f64, self.b as f64, self.c as f64);
+        let cx = beta.cos();
+        let cy = (alpha.cos() - beta.cos() * gamma.cos()) / gamma.sin();
+        let cz = (1.0 - cx * cx - cy * cy).max(0.0).sqrt();
+        [
+            [a, 0.0, 0.0],
+            [b * gamma.cos(), b * gamma.sin(), 0.0],
+            [c * cx, c * cy, c * cz],
+        ]
+        .map(|v| v.map(|x| x as f32))
+    }
+
+    pub fn fractional_to_cartesian(&self, f: [f32; 3]) -> [f32; 3] {
+        let [a, b, c] = self.vectors();
+        [0, 1, 2].map(|i| f[0] * a[i] + f[1] * b[i] + f[2] * c[i])
+    }
+
+    /// Distance in angstroms between two fractional positions, using the nearest
+    /// periodic image.
+    pub fn distance(&self, p: [f32; 3], q: [f32; 3]) -> f32 {
+        let d = [0, 1, 2].map(|i| {
+            let d = p[i] - q[i];
+            d - d.round()
+        });
+        let [x, y, z] = self.fractional_to_cartesian(d);
+        (x * x + y * y + z * z).sqrt()
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            a: 1.0,
+            b: 1.0,
+            c: 1.0,
+            alpha: 90.0,
+            beta: 90.0,
+            gamma: 90.0,
+        }
+    }
+}
+
+impl Site {
+    /// From the type symbol if there is one, otherwise from the leading letters of the
+    /// label, so `Cl2` is chlorine and `C12` is carbon.
+    pub fn element(&self) -> &'static element::Element {
+        let name = self.type_symbol.as_deref().unwrap_or(&self.label);
+        let letters = name
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .map_or(name, |end| &name[..end]);
+        letters
+            .get(..2)
+            .and_then(element::lookup)
+            .unwrap_or_else(|| element::lookup_or_unknown(letters.get(..1).unwrap_or_default()))
+    }
+}
+
+impl SymOp {
+    pub const IDENTITY: SymOp = SymOp {
+        rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        translation: [0.0; 3],
+    };
+
+    pub fn apply(&self, f: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|i| {
+            let r = self.rotation[i];
+            r[0] * f[0] + r[1] * f[1] + r[2] * f[2] + self.translation[i]
+        })
+    }
+}
+
+impl FromStr for SymOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut op = SymOp {
+            rotation: [[0.0; 3]; 3],
+            translation: [0.0; 3],
+        };
+        let mut parts = s.split(',');
+        for i in 0..3 {
+            let part = parts.next().ok_or(())?;
+            (op.rotation[i], op.translation[i]) = parse_component(part).ok_or(())?;
+        }
+        if parts.next().is_some() {
+            return Err(());
+        }
+        Ok(op)
+    }
+}
+
+/// One coordinate of an operator: a sum of terms like `-x`, `1/2`, `0.25` or `2y`.
+fn parse_component(s: &str) -> Option<([f32; 3], f32)> {
+    let s: String = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let mut rest = s.as_str();
+    if rest.is_empty() {
+        return None;
+    }
+
+    let mut coefficients = [0.0; 3];
+    let mut translation = 0.0;
+    while !rest.is_empty() {
+        let sign = match rest.as_bytes()[0] {
+            b'-' => -1.0,
+            _ => 1.0,
+        };
+        rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '/'))
+            .unwrap_or(rest.len());
+        let number = (len > 0).then(|| parse_fraction(&rest[..len])).flatten();
+        if len > 0 && number.is_none() {
+            return None;
+        }
+        rest = &rest[len..];
+        if number.is_some() {
+            rest = rest.strip_prefix('*').unwrap_or(rest);
+        }
+
+        let axis = match rest.as_bytes().first() {
+            Some(b'x') => Some(0),
+            Some(b'y') => Some(1),
+            Some(b'z') => Some(2),
+            _ => None,
+        };
+        match (number, axis) {
+            (number, Some(axis)) => {
+                coefficients[axis] += sign * number.unwrap_or(1.0);
+                rest = &rest[1..];
+            }
+            (Some(number), None) => translation += sign * number,
+            (None, None) => return None,
+        }
+    }
+    Some((coefficients, translation))
+}
+
+fn parse_fraction(s: &str) -> Option<f32> {
+    match s.split_once('/') {
+        Some((n, d)) => Some(n.parse::<f32>().ok()? / d.parse::<f32>().ok()?),
+        None => s.parse().ok(),
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Crystal>, Error> {
+    parse(&std::fs::read_to_string(path).map_err(Error::Io)?)
+}
+
+/// One crystal per data block that has atom sites.
+pub fn parse(src: &str) -> Result<Vec<Crystal>, Error> {
+    let mut tokens = Tokenizer::new(src);
+    let mut blocks: Vec<Block> = vec![];
+    let mut pending = None;
+
+    while let Some(token) = pending
+        .take()
+        .map(Ok)
+        .or_else(|| tokens.next())
+        .transpose()?
+    {
+        match token {
+            Token::Data(name) => blocks.push(Block::new(name)),
+            Token::Tag(tag) => {
+                let next = tokens.next().transpose()?;
+                let (Some(Token::Value(value)), Some(block)) = (next, blocks.last_mut()) else {
+                    pending = next;
+                    continue;
+                };
+                block
+                    .item(&normalize(tag), value)
+                    .map_err(|k| tokens.error(k))?;
+            }
+            Token::Loop => {
+                let mut tags = vec![];
+                let mut next = tokens.next().transpose()?;
+                while let Some(Token::Tag(tag)) = next {
+                    tags.push(normalize(tag));
+                    next = tokens.next().transpose()?;
+                }
+
+                let mut kind = LoopKind::new(&tags).map_err(|k| tokens.error(k))?;
+                let mut row = Vec::with_capacity(tags.len());
+                while let Some(Token::Value(value)) = next {
+                    row.push(value);
+                    if row.len() == tags.len() {
+                        if let Some(block) = blocks.last_mut() {
+                            block.row(&mut kind, &row).map_err(|k| tokens.error(k))?;
+                        }
+                        row.clear();
+                    }
+                    next = tokens.next().transpose()?;
+                }
+                if !row.is_empty() {
+                    return Err(tokens.error(ErrorKind::RaggedLoop));
+                }
+                pending = next;
+            }
+            _ => {}
+        }
+    }
+
+    blocks
+        .into_iter()
+        .filter(|b| !b.crystal.sites.is_empty())
+        .map(|b| b.finish().map_err(|k| tokens.error(k)))
+        .collect()
+}
+
+/// Small-molecule CIF uses `_atom_site_fract_x` while newer files use the DDL2 form
+/// `_atom_site.fract_x`. Both become the former.
+fn normalize(tag: &str) -> String {
+    tag.to_ascii_lowercase().replace('.', "_")
+}
+
+const CELL_TAGS: [&str; 6] = [
+    "_cell_length_a",
+    "_cell_length_b",
+    "_cell_length_c",
+    "_cell_angle_alpha",
+    "_cell_angle_beta",
+    "_cell_angle_gamma",
+];
+
+const SYMMETRY_TAGS: [&str; 2] = [
+    "_symmetry_equiv_pos_as_xyz",
+    "_space_group_symop_operation_xyz",
+];
+
+struct Block {
+    crystal: Crystal,
+    cell: [Option<f32>; 6],
+}
+
+/// What a loop holds, with the index of each column of interest within a row.
+enum LoopKind {
+    Sites {
+        label: usize,
+        type_symbol: Option<usize>,
+        fract: [usize; 3],
+        occupancy: Option<usize>,
+    },
+    Symmetry(usize),
+    Other,
+}
+
+impl Block {
+    fn new(name: &str) -> Self {
+        Self {
+            crystal: Crystal {
+                name: name.to_string(),
+                ..Default::default()
+            },
+            cell: [None; 6],
+        }
+    }
+
+    fn item(&mut self, tag: &str, value: Value) -> Result<(), ErrorKind> {
+        if let Some(i) = CELL_TAGS.iter().position(|t| *t == tag) {
+            self.cell[i] = Some(number(value, CELL_TAGS[i])?);
+        } else if let Some(i) = SYMMETRY_TAGS.iter().position(|t| *t == tag) {
+            self.crystal.symmetry.push(sym_op(value, SYMMETRY_TAGS[i])?);
+        }
+        Ok(())
+    }
+
+    fn row(&mut self, kind: &mut LoopKind, row: &[Value]) -> Result<(), ErrorKind> {
+        match *kind {
+            LoopKind::Sites {
+                label,
+                type_symbol,
+                fract,
+                occupancy,
+            } => self.crystal.sites.push(Site {
+                label: row[label].text.to_string(),
+                type_symbol: type_symbol
+                    .and_then(|i| row[i].non_null())
+                    .map(str::to_string),
+                fract: [
+                    number(row[fract[0]], "_atom_site_fract_x")?,
+                    number(row[fract[1]], "_atom_site_fract_y")?,
+                    number(row[fract[2]], "_atom_site_fract_z")?,
+                ],
+                occupancy: match occupancy {
+                    Some(i) if !row[i].is_null() => number(row[i], "_atom_site_occupancy")?,
+                    _ => 1.0,
+                },
+            }),
+            LoopKind::Symmetry(column) => {
+                let tag = SYMMETRY_TAGS[0];
+                self.crystal.symmetry.push(sym_op(row[column], tag)?);
+            }
+            LoopKind::Other => {}
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Crystal, ErrorKind> {
+        let mut cell = [0.0; 6];
+        for (i, value) in self.cell.iter().enumerate() {
+            cell[i] = value.ok_or(ErrorKind::MissingTag(CELL_TAGS[i]))?;
+        }
+        let [a, b, c, alpha, beta, gamma] = cell;
+        self.crystal.cell = Cell {
+            a,
+            b,
+            c,
+            alpha,
+            beta,
+            gamma,
+        };
+        Ok(self.crystal)
+    }
+}
+
+impl LoopKind {
+    fn new(tags: &[String]) -> Result<Self, ErrorKind> {
+        let find = |name: &str| tags.iter().position(|t| t == name);
+        if let Some(column) = SYMMETRY_TAGS.iter().find_map(|t| find(t)) {
+            return Ok(LoopKind::Symmetry(column));
+        }
+        let Some(x) = find("_atom_site_fract_x") else {
+            return Ok(LoopKind::Other);
+        };
+        let req = |name: &'static str| find(name).ok_or(ErrorKind::MissingTag(name));
+        Ok(LoopKind::Sites {
+            label: req("_atom_site_label")?,
+            type_symbol: find("_atom_site_type_symbol"),
+            fract: [x, req("_atom_site_fract_y")?, req("_atom_site_fract_z")?],
+            occupancy: find("_atom_site_occupancy"),
+        })
+    }
+}
+
+/// Numbers may carry a standard uncertainty in parentheses, as in `10.2345(12)`.
+fn number(value: Value, tag: &'static str) -> Result<f32, ErrorKind> {
+    let text = value.text.split('(').next().unwrap_or_default();
+    text.parse().map_err(|_| ErrorKind::InvalidValue {
+        tag,
+        value: value.text.to_string(),
+    })
+}
+
+fn sym_op(value: Value, tag: &'static str) -> Result<SymOp, ErrorKind> {
+    value.text.parse().map_err(|_| ErrorKind::InvalidValue {
+        tag,
+        value: value.text.to_string(),
+    })
+}
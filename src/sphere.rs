@@ -0,0 +1,98 @@
+//! Triangle meshes of spheres, for exporting atoms to formats that have no impostors.
+
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::{render_pipeline::AtomCpu, volume::Mesh};
+
+/// A unit sphere made by splitting each face of an icosahedron in four `subdivisions`
+/// times, pushing the new vertices out onto the sphere. Zero subdivisions give the 20
+/// faces of the icosahedron itself.
+pub fn icosphere(subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|p| Vec3::from(p).normalize())
+    .collect();
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // edges are shared by two triangles, which must share the midpoint too
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(p);
+                positions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    Mesh {
+        normals: positions.iter().map(|&p| p.into()).collect(),
+        positions: positions.into_iter().map(Into::into).collect(),
+        indices: triangles.into_iter().flatten().collect(),
+    }
+}
+
+/// Copies of `sphere`, a unit sphere such as [`icosphere`] gives, scaled and moved to
+/// cover each of `atoms`, in one mesh. The vertices of atom `n` come `n`th, in blocks
+/// the size of `sphere`.
+pub fn bake(sphere: &Mesh, atoms: &[AtomCpu]) -> Mesh {
+    let mut mesh = Mesh::default();
+    for atom in atoms {
+        let base = mesh.positions.len() as u32;
+        let center = Vec3::from(atom.pos);
+        mesh.positions.extend(
+            sphere
+                .positions
+                .iter()
+                .map(|&p| <[f32; 3]>::from(Vec3::from(p) * atom.radius + center)),
+        );
+        mesh.normals.extend_from_slice(&sphere.normals);
+        mesh.indices.extend(sphere.indices.iter().map(|i| base + i));
+    }
+    mesh
+}
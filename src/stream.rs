@@ -0,0 +1,406 @@
+//! Atoms streamed into the viewer over a socket while a simulation runs.
+//!
+//! A stream is a sequence of messages, each a header followed by records, all little
+//! endian:
+//!
+//! ```text
+//! 0   magic "BDDS"
+//! 4   version: u8, currently 1
+//! 5   kind: u8, 1 for atoms or 2 for positions
+//! 6   reserved: u16, zero
+//! 8   count: u32, the number of records
+//! 12  first: u32, the index of the first atom positions are for, zero for atoms
+//! 16  records
+//! ```
+//!
+//! Atom records are laid out as [`AtomCpu`]: position, color and radius, seven f32s.
+//! They replace every atom shown. Position records are three f32s each and move atoms
+//! `first` onwards, leaving colors and radii alone, which is all a simulation usually
+//! needs to send after the first frame.
+
+use std::{
+    fmt,
+    io::{self, BufReader, Read, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use crate::render_pipeline::AtomCpu;
+
+const MAGIC: &[u8; 4] = b"BDDS";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+const ATOMS: u8 = 1;
+const POSITIONS: u8 = 2;
+const ATOM_LEN: usize = 28;
+const POSITION_LEN: usize = 12;
+
+#[derive(Clone)]
+pub enum Message {
+    /// Every atom, replacing those shown.
+    Atoms(Vec<AtomCpu>),
+    /// New positions for atoms `first` onwards.
+    Positions {
+        first: u32,
+        positions: Vec<[f32; 3]>,
+    },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// `offset` counts bytes from the start of the connection.
+    Invalid {
+        offset: usize,
+        kind: ErrorKind,
+    },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The stream is not at the start of a message, likely after a bad record count.
+    BadMagic([u8; 4]),
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    /// The connection closed partway through a message.
+    Truncated,
+    /// Positions for atoms that are not there.
+    OutOfRange {
+        first: u32,
+        count: usize,
+        atoms: usize,
+    },
+}
+
+impl Message {
+    /// Apply the message to the atoms shown.
+    pub fn apply(self, atoms: &mut Vec<AtomCpu>) -> Result<(), ErrorKind> {
+        match self {
+            Message::Atoms(new) => *atoms = new,
+            Message::Positions { first, positions } => {
+                let range = first as usize..first as usize + positions.len();
+                let Some(atoms) = atoms.get_mut(range) else {
+                    return Err(ErrorKind::OutOfRange {
+                        first,
+                        count: positions.len(),
+                        atoms: atoms.len(),
+                    });
+                };
+                for (atom, pos) in atoms.iter_mut().zip(positions) {
+                    atom.pos = pos;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads messages from one connection.
+pub struct Reader<R> {
+    inner: R,
+    offset: usize,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, offset: 0 }
+    }
+
+    /// The next message, or `None` when the connection closes between messages.
+    pub fn next_message(&mut self) -> Result<Option<Message>, Error> {
+        let start = self.offset;
+        let invalid = |kind| Error::Invalid {
+            offset: start,
+            kind,
+        };
+
+        let header = self.read(HEADER_LEN)?;
+        if header.is_empty() {
+            return Ok(None);
+        }
+        if header.len() < HEADER_LEN {
+            return Err(invalid(ErrorKind::Truncated));
+        }
+        let magic: [u8; 4] = header[..4].try_into().unwrap();
+        if &magic != MAGIC {
+            return Err(invalid(ErrorKind::BadMagic(magic)));
+        }
+        if header[4] != VERSION {
+            return Err(invalid(ErrorKind::UnsupportedVersion(header[4])));
+        }
+        let count = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let first = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        let record_len = match header[5] {
+            ATOMS => ATOM_LEN,
+            POSITIONS => POSITION_LEN,
+            kind => return Err(invalid(ErrorKind::UnknownKind(kind))),
+        };
+
+        let records = self.read(count * record_len)?;
+        if records.len() < count * record_len {
+            return Err(invalid(ErrorKind::Truncated));
+        }
+        let records = records.chunks_exact(record_len).map(floats);
+        Ok(Some(match header[5] {
+            ATOMS => Message::Atoms(
+                records
+                    .map(|f| AtomCpu {
+                        pos: [f[0], f[1], f[2]],
+                        color: [f[3], f[4], f[5]],
+                        radius: f[6],
+                    })
+                    .collect(),
+            ),
+            _ => Message::Positions {
+                first,
+                positions: records.map(|f| [f[0], f[1], f[2]]).collect(),
+            },
+        }))
+    }
+
+    /// Up to `len` bytes, fewer only if the connection closes. The buffer grows as data
+    /// arrives, so a garbage count cannot make it allocate everything up front.
+    fn read(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![];
+        (&mut self.inner)
+            .take(len as u64)
+            .read_to_end(&mut buf)
+            .map_err(Error::Io)?;
+        self.offset += buf.len();
+        Ok(buf)
+    }
+}
+
+fn floats(record: &[u8]) -> Vec<f32> {
+    record
+        .chunks_exact(4)
+        .map(|f| f32::from_le_bytes(f.try_into().unwrap()))
+        .collect()
+}
+
+/// Write a message, for simulations and tests on the sending side.
+pub fn write_message(mut w: impl Write, message: &Message) -> io::Result<()> {
+    let (kind, count, first, floats): (u8, usize, u32, Vec<f32>) = match message {
+        Message::Atoms(atoms) => (
+            ATOMS,
+            atoms.len(),
+            0,
+            bytemuck::cast_slice(atoms.as_slice()).to_vec(),
+        ),
+        Message::Positions { first, positions } => (
+            POSITIONS,
+            positions.len(),
+            *first,
+            positions.iter().flatten().copied().collect(),
+        ),
+    };
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION, kind, 0, 0])?;
+    w.write_all(&(count as u32).to_le_bytes())?;
+    w.write_all(&first.to_le_bytes())?;
+    for f in floats {
+        w.write_all(&f.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Accepts connections one at a time on a background thread, so that waiting for
+/// data never holds up drawing.
+pub struct Listener {
+    messages: Receiver<Result<Message, Error>>,
+}
+
+impl Listener {
+    /// Listen on `address`, either `host:port` for TCP or `unix:path` for a Unix socket.
+    pub fn bind(address: &str) -> io::Result<Self> {
+        let (sender, messages) = mpsc::channel();
+        match address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                thread::spawn(move || serve(listener.incoming(), sender));
+            }
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "unix sockets are not available on this platform",
+                ))
+            }
+            None => {
+                let listener = TcpListener::bind(address)?;
+                thread::spawn(move || serve(listener.incoming(), sender));
+            }
+        }
+        Ok(Self { messages })
+    }
+
+    /// Everything received since the last call, without waiting for more. Errors end
+    /// their connection, and the listener goes on to accept the next.
+    pub fn received(&self) -> impl Iterator<Item = Result<Message, Error>> + '_ {
+        self.messages.try_iter()
+    }
+}
+
+fn serve<S: Read>(
+    incoming: impl Iterator<Item = io::Result<S>>,
+    sender: Sender<Result<Message, Error>>,
+) {
+    for connection in incoming {
+        let mut reader = match connection {
+            Ok(connection) => Reader::new(BufReader::new(connection)),
+            Err(e) => {
+                if sender.send(Err(Error::Io(e))).is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        loop {
+            let message = match reader.next_message() {
+                Ok(Some(message)) => Ok(message),
+                Ok(None) => break,
+                Err(e) => Err(e),
+            };
+            let failed = message.is_err();
+            // the viewer is gone once nothing is receiving
+            if sender.send(message).is_err() {
+                return;
+            }
+            if failed {
+                break;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Invalid { offset, kind } => write!(f, "byte {offset}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::BadMagic(magic) => write!(f, "expected a message but found {magic:?}"),
+            ErrorKind::UnsupportedVersion(v) => write!(f, "unsupported protocol version {v}"),
+            ErrorKind::UnknownKind(k) => write!(f, "unknown message kind {k}"),
+            ErrorKind::Truncated => write!(f, "connection closed partway through a message"),
+            ErrorKind::OutOfRange {
+                first,
+                count,
+                atoms,
+            } => write!(
+                f,
+                "positions for {count} atoms from atom {first} but only {atoms} atoms are shown"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atoms() -> Vec<AtomCpu> {
+        (0..3)
+            .map(|n| AtomCpu {
+                pos: [n as f32, 1.0, 2.0],
+                color: [0.1, 0.2, n as f32 / 3.0],
+                radius: 1.5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = vec![];
+        write_message(&mut bytes, &Message::Atoms(atoms())).unwrap();
+        let positions = Message::Positions {
+            first: 1,
+            positions: vec![[7.0, 8.0, 9.0], [-1.0, -2.0, -3.0]],
+        };
+        write_message(&mut bytes, &positions).unwrap();
+
+        let mut reader = Reader::new(bytes.as_slice());
+        let mut shown = vec![];
+        while let Some(message) = reader.next_message().unwrap() {
+            message.apply(&mut shown).unwrap();
+        }
+
+        let expected = atoms();
+        assert_eq!(shown.len(), 3);
+        assert_eq!(shown[0].pos, expected[0].pos);
+        assert_eq!(shown[1].pos, [7.0, 8.0, 9.0]);
+        assert_eq!(shown[2].pos, [-1.0, -2.0, -3.0]);
+        for (shown, expected) in shown.iter().zip(&expected) {
+            assert_eq!(shown.color, expected.color);
+            assert_eq!(shown.radius, expected.radius);
+        }
+    }
+
+    #[test]
+    fn positions_past_the_end_are_rejected() {
+        let mut shown = atoms();
+        let message = Message::Positions {
+            first: 2,
+            positions: vec![[0.0; 3]; 2],
+        };
+        assert!(matches!(
+            message.apply(&mut shown),
+            Err(ErrorKind::OutOfRange {
+                first: 2,
+                count: 2,
+                atoms: 3
+            })
+        ));
+        assert_eq!(shown[2].pos, atoms()[2].pos);
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let mut bytes = vec![];
+        write_message(&mut bytes, &Message::Atoms(atoms())).unwrap();
+        let len = bytes.len();
+
+        let error = |bytes: &[u8]| match Reader::new(bytes).next_message() {
+            Err(Error::Invalid { offset, kind }) => (offset, kind),
+            Err(e) => panic!("expected invalid data, got {e}"),
+            Ok(_) => panic!("expected invalid data"),
+        };
+        assert!(matches!(
+            error(&bytes[..len - 1]),
+            (0, ErrorKind::Truncated)
+        ));
+        assert!(matches!(error(&bytes[..10]), (0, ErrorKind::Truncated)));
+
+        let mut wrong = bytes.clone();
+        wrong[5] = 9;
+        assert!(matches!(error(&wrong), (0, ErrorKind::UnknownKind(9))));
+        wrong[4] = 2;
+        assert!(matches!(
+            error(&wrong),
+            (0, ErrorKind::UnsupportedVersion(2))
+        ));
+
+        // a second message after garbage is reported where it starts
+        let mut garbage = bytes.clone();
+        garbage.extend(b"not a message at all");
+        let mut reader = Reader::new(garbage.as_slice());
+        assert!(reader.next_message().unwrap().is_some());
+        assert!(matches!(
+            reader.next_message(),
+            Err(Error::Invalid {
+                offset,
+                kind: ErrorKind::BadMagic(_),
+            }) if offset == len
+        ));
+    }
+}
@@ -0,0 +1,433 @@
+//! Readers for VASP structure files: POSCAR and CONTCAR, which hold one structure, and
+//! XDATCAR, which holds a trajectory.
+//!
+//! Both VASP 4 files without a species line and VASP 5 files with one are accepted.
+//! Positions are always converted to Cartesian angstroms.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Structure {
+    pub comment: String,
+    /// Lattice vectors in angstroms with the scale factor applied, one per row.
+    pub lattice: [[f32; 3]; 3],
+    pub atoms: Vec<Atom>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    /// Name from the species line. May carry a POTCAR suffix such as `Fe_pv`.
+    pub species: String,
+    /// Cartesian, angstroms
+    pub pos: [f32; 3],
+    /// Whether each coordinate is allowed to move, when selective dynamics is on.
+    pub selective: Option<[bool; 3]>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The file ended in the middle of a structure.
+    Truncated,
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    /// The species line and the counts line have different lengths.
+    SpeciesCount {
+        species: usize,
+        counts: usize,
+    },
+    /// An XDATCAR configuration before any header.
+    NoHeader,
+}
+
+impl Structure {
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms.iter().map(Atom::to_atom_cpu).collect()
+    }
+
+    /// Convert fractional coordinates to Cartesian angstroms.
+    pub fn fractional_to_cartesian(&self, f: [f32; 3]) -> [f32; 3] {
+        fractional_to_cartesian(&self.lattice, f)
+    }
+
+    /// The twelve edges of the unit cell as pairs of end points, for drawing the cell
+    /// next to the atoms.
+    pub fn cell_edges(&self) -> [[[f32; 3]; 2]; 12] {
+        let corner = |i: u8| {
+            let f = [i & 1, (i >> 1) & 1, (i >> 2) & 1].map(f32::from);
+            self.fractional_to_cartesian(f)
+        };
+        // corners that differ in exactly one bit share an edge
+        let mut edges = [[[0.0; 3]; 2]; 12];
+        let pairs = (0..8u8).flat_map(|i| [1, 2, 4].map(|bit| (i, i | bit)));
+        for (edge, (a, b)) in edges.iter_mut().zip(pairs.filter(|(a, b)| a != b)) {
+            *edge = [corner(a), corner(b)];
+        }
+        edges
+    }
+}
+
+impl Atom {
+    pub fn element(&self) -> &'static element::Element {
+        let symbol = self
+            .species
+            .split(['_', '/', '.'])
+            .next()
+            .unwrap_or_default();
+        element::lookup_or_unknown(symbol)
+    }
+
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = self.element();
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+/// Everything before the coordinates. XDATCAR files from variable cell runs repeat it
+/// for every frame.
+#[derive(Clone)]
+struct Header {
+    comment: String,
+    lattice: [[f32; 3]; 3],
+    /// Per-axis factors that also apply to Cartesian coordinates.
+    scale: [f32; 3],
+    /// species name for each atom
+    species: Vec<String>,
+}
+
+/// Reads POSCAR files with [`Reader::read_poscar`], or iterates over the frames of an
+/// XDATCAR file.
+pub struct Reader<R> {
+    inner: R,
+    line: usize,
+    buf: String,
+    header: Option<Header>,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(
+            File::open(path).map_err(Error::Io)?,
+        )))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 0,
+            buf: String::new(),
+            header: None,
+        }
+    }
+
+    /// Returns false at end of input.
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.buf.clear();
+        let read = self.inner.read_line(&mut self.buf).map_err(Error::Io)?;
+        self.line += 1;
+        let trimmed = self.buf.trim_end_matches(['\n', '\r']).len();
+        self.buf.truncate(trimmed);
+        Ok(read != 0)
+    }
+
+    fn require_line(&mut self) -> Result<&str, Error> {
+        if self.next_line()? {
+            Ok(&self.buf)
+        } else {
+            Err(self.error(ErrorKind::Truncated))
+        }
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    /// Read a single POSCAR or CONTCAR structure. Anything after the positions, such
+    /// as the velocities in a CONTCAR, is ignored.
+    pub fn read_poscar(&mut self) -> Result<Structure, Error> {
+        let line = self.require_line()?.to_string();
+        let header = self.read_header(line)?;
+
+        let mut line = self.require_line()?;
+        let selective = line.trim_start().starts_with(['s', 'S']);
+        if selective {
+            line = self.require_line()?;
+        }
+        let cartesian = is_cartesian(line);
+
+        self.read_positions(&header, cartesian, selective)
+    }
+
+    /// `line` is the comment line, which has already been read.
+    fn read_header(&mut self, comment: String) -> Result<Header, Error> {
+        let line = self.require_line()?;
+        let factors = parse_floats(line)
+            .filter(|f| matches!(f.len(), 1 | 3))
+            .ok_or_else(|| ErrorKind::InvalidField {
+                field: "scale",
+                value: line.to_string(),
+            })
+            .map_err(|k| self.error(k))?;
+
+        let mut lattice = [[0.0; 3]; 3];
+        for vector in &mut lattice {
+            let line = self.require_line()?;
+            *vector = parse_vector(line, "lattice vector").map_err(|k| self.error(k))?;
+        }
+
+        let scale = match *factors.as_slice() {
+            // a negative factor is the cell volume
+            [s] if s < 0.0 => [(-s / determinant(&lattice).abs()).cbrt(); 3],
+            [s] => [s; 3],
+            _ => [factors[0], factors[1], factors[2]],
+        };
+        for vector in &mut lattice {
+            for (v, s) in vector.iter_mut().zip(scale) {
+                *v *= s;
+            }
+        }
+
+        // VASP 5 has a line of species names before the counts
+        let line = self.require_line()?;
+        let names: Option<Vec<String>> = if line
+            .split_whitespace()
+            .next()
+            .is_some_and(|t| t.parse::<usize>().is_err())
+        {
+            let names = line.split_whitespace().map(str::to_string).collect();
+            self.require_line()?;
+            Some(names)
+        } else {
+            None
+        };
+        let counts = self
+            .buf
+            .split_whitespace()
+            .map(|t| t.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                self.error(ErrorKind::InvalidField {
+                    field: "atom counts",
+                    value: self.buf.clone(),
+                })
+            })?;
+
+        // VASP 4 files conventionally list the species on the comment line instead
+        let names = names.unwrap_or_else(|| {
+            let from_comment: Vec<String> =
+                comment.split_whitespace().map(str::to_string).collect();
+            if from_comment.len() == counts.len() {
+                from_comment
+            } else {
+                vec!["X".to_string(); counts.len()]
+            }
+        });
+        if names.len() != counts.len() {
+            return Err(self.error(ErrorKind::SpeciesCount {
+                species: names.len(),
+                counts: counts.len(),
+            }));
+        }
+
+        let species = names
+            .iter()
+            .zip(&counts)
+            .flat_map(|(name, &count)| std::iter::repeat_n(name.clone(), count))
+            .collect();
+
+        Ok(Header {
+            comment,
+            lattice,
+            scale,
+            species,
+        })
+    }
+
+    fn read_positions(
+        &mut self,
+        header: &Header,
+        cartesian: bool,
+        selective: bool,
+    ) -> Result<Structure, Error> {
+        let mut structure = Structure {
+            comment: header.comment.clone(),
+            lattice: header.lattice,
+            atoms: Vec::with_capacity(header.species.len()),
+        };
+        for species in &header.species {
+            let line = self.require_line()?;
+            let atom = parse_atom(line, species, selective).map_err(|k| self.error(k))?;
+            structure.atoms.push(atom);
+        }
+        for atom in &mut structure.atoms {
+            atom.pos = if cartesian {
+                [0, 1, 2].map(|i| atom.pos[i] * header.scale[i])
+            } else {
+                fractional_to_cartesian(&header.lattice, atom.pos)
+            };
+        }
+        Ok(structure)
+    }
+
+    /// The next XDATCAR frame.
+    fn read_frame(&mut self) -> Result<Option<Structure>, Error> {
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            if self.buf.trim().is_empty() {
+                continue;
+            }
+            // frames start with `Direct configuration=     1`; anything else is a header
+            let lowercase = self.buf.trim_start().to_ascii_lowercase();
+            if lowercase.starts_with("direct") || lowercase.starts_with("cartesian") {
+                let cartesian = is_cartesian(&self.buf);
+                let header = self
+                    .header
+                    .take()
+                    .ok_or_else(|| self.error(ErrorKind::NoHeader))?;
+                let frame = self.read_positions(&header, cartesian, false);
+                self.header = Some(header);
+                return frame.map(Some);
+            }
+            let comment = self.buf.clone();
+            self.header = Some(self.read_header(comment)?);
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Structure, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+pub fn parse_poscar(src: &str) -> Result<Structure, Error> {
+    Reader::new(src.as_bytes()).read_poscar()
+}
+
+pub fn load_poscar(path: impl AsRef<Path>) -> Result<Structure, Error> {
+    Reader::open(path)?.read_poscar()
+}
+
+pub fn parse_xdatcar(src: &str) -> Result<Vec<Structure>, Error> {
+    Reader::new(src.as_bytes()).collect()
+}
+
+pub fn load_xdatcar(path: impl AsRef<Path>) -> Result<Vec<Structure>, Error> {
+    Reader::open(path)?.collect()
+}
+
+/// `Cartesian` and `Kartesian` both select Cartesian coordinates, anything else is
+/// fractional.
+fn is_cartesian(line: &str) -> bool {
+    line.trim_start().starts_with(['c', 'C', 'k', 'K'])
+}
+
+/// `x y z [T F T]`, possibly followed by a label which is ignored.
+fn parse_atom(line: &str, species: &str, selective: bool) -> Result<Atom, ErrorKind> {
+    let pos = parse_vector(line, "position")?;
+    let selective = if selective {
+        let mut flags = line.split_whitespace().skip(3).map(|t| match t {
+            "T" | "t" => Ok(true),
+            "F" | "f" => Ok(false),
+            _ => Err(ErrorKind::InvalidField {
+                field: "selective dynamics",
+                value: line.to_string(),
+            }),
+        });
+        let mut flag = || {
+            flags.next().unwrap_or(Err(ErrorKind::InvalidField {
+                field: "selective dynamics",
+                value: line.to_string(),
+            }))
+        };
+        Some([flag()?, flag()?, flag()?])
+    } else {
+        None
+    };
+    Ok(Atom {
+        species: species.to_string(),
+        pos,
+        selective,
+    })
+}
+
+/// The first three numbers on a line.
+fn parse_vector(line: &str, field: &'static str) -> Result<[f32; 3], ErrorKind> {
+    let mut values = line.split_whitespace().map(str::parse::<f32>);
+    let mut value = || match values.next() {
+        Some(Ok(v)) => Ok(v),
+        _ => Err(ErrorKind::InvalidField {
+            field,
+            value: line.to_string(),
+        }),
+    };
+    Ok([value()?, value()?, value()?])
+}
+
+fn parse_floats(line: &str) -> Option<Vec<f32>> {
+    line.split_whitespace().map(|t| t.parse().ok()).collect()
+}
+
+fn fractional_to_cartesian(lattice: &[[f32; 3]; 3], f: [f32; 3]) -> [f32; 3] {
+    let [a, b, c] = lattice;
+    [0, 1, 2].map(|i| f[0] * a[i] + f[1] * b[i] + f[2] * c[i])
+}
+
+fn determinant(m: &[[f32; 3]; 3]) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Truncated => write!(f, "file ends in the middle of a structure"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::SpeciesCount { species, counts } => {
+                write!(f, "{species} species names but {counts} atom counts")
+            }
+            ErrorKind::NoHeader => write!(f, "configuration before the first header"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,260 @@
+//! Scalar fields sampled on a regular grid, and isosurface extraction by marching cubes.
+//!
+//! The marching cubes case table is derived from the cell faces when first needed rather
+//! than written out. On faces where the surface could be drawn two ways, inside corners
+//! are always kept apart, so that neighbouring cells agree and surfaces come out closed.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use glam::{Mat3, Vec3};
+
+/// Values on a grid of points `origin + i * axes[0] + j * axes[1] + k * axes[2]`. The
+/// axes need not be orthogonal.
+#[derive(Clone, Debug, Default)]
+pub struct Grid {
+    /// number of points along each axis
+    pub shape: [usize; 3],
+    /// angstroms
+    pub origin: [f32; 3],
+    /// Step between neighbouring points along each axis, in angstroms.
+    pub axes: [[f32; 3]; 3],
+    /// Indexed by `(i * shape[1] + j) * shape[2] + k`, so the last axis varies fastest.
+    pub values: Vec<f32>,
+}
+
+/// Triangles with per-vertex normals.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    /// unit length, pointing away from the enclosed region
+    pub normals: Vec<[f32; 3]>,
+    /// three per triangle
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Each edge of the triangles once, as pairs of vertex indices.
+    pub fn edges(&self) -> Vec<[u32; 2]> {
+        let mut edges: Vec<[u32; 2]> = self
+            .indices
+            .chunks(3)
+            .flat_map(|t| [[t[0], t[1]], [t[1], t[2]], [t[2], t[0]]])
+            .map(|[a, b]| [a.min(b), a.max(b)])
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+}
+
+impl Grid {
+    pub fn index(&self, [i, j, k]: [usize; 3]) -> usize {
+        (i * self.shape[1] + j) * self.shape[2] + k
+    }
+
+    pub fn get(&self, point: [usize; 3]) -> f32 {
+        self.values[self.index(point)]
+    }
+
+    /// Position of a grid point in angstroms.
+    pub fn position(&self, [i, j, k]: [usize; 3]) -> Vec3 {
+        Vec3::from(self.origin)
+            + Vec3::from(self.axes[0]) * i as f32
+            + Vec3::from(self.axes[1]) * j as f32
+            + Vec3::from(self.axes[2]) * k as f32
+    }
+
+    /// Mean and standard deviation of the values.
+    pub fn statistics(&self) -> (f32, f32) {
+        if self.values.is_empty() {
+            return (0.0, 0.0);
+        }
+        let n = self.values.len() as f64;
+        let mean = self.values.iter().map(|&v| v as f64).sum::<f64>() / n;
+        let variance = self
+            .values
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        (mean as f32, variance.sqrt() as f32)
+    }
+
+    /// The surface where the field crosses `level`. For positive levels the enclosed
+    /// region is where values are above the level, for negative levels where they are
+    /// below it, so that `level` and `-level` give the two lobes of an orbital.
+    pub fn isosurface(&self, level: f32) -> Mesh {
+        self.isosurface_in(level, [0, 0, 0], self.shape)
+    }
+
+    /// Like [`Grid::isosurface`] but only marches the cells between grid points `from`
+    /// and `to`, exclusive.
+    pub fn isosurface_in(&self, level: f32, from: [usize; 3], to: [usize; 3]) -> Mesh {
+        let inside = |v: f32| if level < 0.0 { v < level } else { v > level };
+        // the gradient points towards higher values, which is outward for negative levels
+        let outward = if level < 0.0 { 1.0 } else { -1.0 };
+        let to: [usize; 3] = std::array::from_fn(|a| to[a].min(self.shape[a]));
+        let to_cartesian = Mat3::from_cols(
+            self.axes[0].into(),
+            self.axes[1].into(),
+            self.axes[2].into(),
+        )
+        .inverse()
+        .transpose();
+
+        let table = case_table();
+        let mut mesh = Mesh::default();
+        // vertices are shared between cells, keyed by grid point and edge direction
+        let mut vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+        for i in from[0]..to[0].saturating_sub(1) {
+            for j in from[1]..to[1].saturating_sub(1) {
+                for k in from[2]..to[2].saturating_sub(1) {
+                    let case = (0..8).fold(0, |case, c| {
+                        let [x, y, z] = corner(c);
+                        case | (inside(self.get([i + x, j + y, k + z])) as usize) << c
+                    });
+                    for triangle in &table[case] {
+                        for &(c, axis) in triangle {
+                            let [x, y, z] = corner(c);
+                            let a = [i + x, j + y, k + z];
+                            let index =
+                                *vertices.entry((self.index(a), axis)).or_insert_with(|| {
+                                    let (pos, normal) = self.crossing(a, axis, level);
+                                    let normal = to_cartesian * normal * outward;
+                                    mesh.positions.push(pos.into());
+                                    mesh.normals.push(normal.normalize_or_zero().into());
+                                    mesh.positions.len() as u32 - 1
+                                });
+                            mesh.indices.push(index);
+                        }
+                    }
+                }
+            }
+        }
+        mesh
+    }
+
+    /// The part of the isosurface within `radius` angstroms of `center`. Only the cells
+    /// around the sphere are marched, so this stays cheap on large maps.
+    pub fn isosurface_near(&self, level: f32, center: [f32; 3], radius: f32) -> Mesh {
+        let to_grid = Mat3::from_cols(
+            self.axes[0].into(),
+            self.axes[1].into(),
+            self.axes[2].into(),
+        )
+        .inverse();
+        let center = Vec3::from(center);
+        let at = to_grid * (center - Vec3::from(self.origin));
+        // half the extent of the sphere along each grid axis, in grid steps
+        let reach: [f32; 3] = std::array::from_fn(|a| radius * to_grid.row(a).length());
+        let from = std::array::from_fn(|a| (at[a] - reach[a]).floor().max(0.0) as usize);
+        let to = std::array::from_fn(|a| (at[a] + reach[a]).ceil().max(0.0) as usize + 1);
+
+        let mut mesh = self.isosurface_in(level, from, to);
+        let within = |i: &u32| center.distance(mesh.positions[*i as usize].into()) <= radius;
+        mesh.indices = mesh
+            .indices
+            .chunks(3)
+            .filter(|triangle| triangle.iter().all(within))
+            .flatten()
+            .copied()
+            .collect();
+        mesh
+    }
+
+    /// Where the field crosses `level` between point `a` and its neighbour along `axis`,
+    /// and the gradient there in grid units.
+    fn crossing(&self, a: [usize; 3], axis: usize, level: f32) -> (Vec3, Vec3) {
+        let mut b = a;
+        b[axis] += 1;
+        let (va, vb) = (self.get(a), self.get(b));
+        let t = (level - va) / (vb - va);
+        (
+            self.position(a) + Vec3::from(self.axes[axis]) * t,
+            self.gradient(a).lerp(self.gradient(b), t),
+        )
+    }
+
+    /// Central differences in grid units, one sided at the edges of the grid.
+    fn gradient(&self, point: [usize; 3]) -> Vec3 {
+        Vec3::from(std::array::from_fn::<f32, 3, _>(|axis| {
+            let mut lo = point;
+            let mut hi = point;
+            lo[axis] = lo[axis].saturating_sub(1);
+            hi[axis] = (hi[axis] + 1).min(self.shape[axis] - 1);
+            if hi[axis] == lo[axis] {
+                return 0.0;
+            }
+            (self.get(hi) - self.get(lo)) / (hi[axis] - lo[axis]) as f32
+        }))
+    }
+}
+
+/// Offset of corner `c` of a cell along each axis.
+fn corner(c: usize) -> [usize; 3] {
+    [c & 1, c >> 1 & 1, c >> 2 & 1]
+}
+
+/// Cell faces, corners counterclockwise as seen from outside the cell.
+const FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// A cell edge as the corner it starts from and the axis it runs along.
+type Edge = (usize, usize);
+
+/// Triangles for each of the 256 ways the corners of a cell can be inside or outside.
+fn case_table() -> &'static [Vec<[Edge; 3]>] {
+    static TABLE: OnceLock<Vec<Vec<[Edge; 3]>>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..256).map(case_triangles).collect())
+}
+
+/// The surface crosses each face in segments that run from an edge where the walk
+/// around the face enters the inside to the edge where it leaves again. Every crossed
+/// edge starts one segment and ends another, so the segments join into closed loops,
+/// which are then split into triangles.
+fn case_triangles(case: usize) -> Vec<[Edge; 3]> {
+    let inside = |c: usize| case >> c & 1 == 1;
+    let edge = |a: usize, b: usize| (a.min(b), (a ^ b).trailing_zeros() as usize);
+    let key = |(c, axis): Edge| c * 3 + axis;
+
+    let mut next: [Option<Edge>; 24] = [None; 24];
+    for face in FACES {
+        let crossings: Vec<(Edge, bool)> = (0..4)
+            .map(|n| (face[n], face[(n + 1) % 4]))
+            .filter(|&(a, b)| inside(a) != inside(b))
+            .map(|(a, b)| (edge(a, b), inside(b)))
+            .collect();
+        for (n, &(start, entering)) in crossings.iter().enumerate() {
+            if entering {
+                next[key(start)] = Some(crossings[(n + 1) % crossings.len()].0);
+            }
+        }
+    }
+
+    let mut triangles = vec![];
+    let mut visited = [false; 24];
+    for start in 0..24 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut polygon = vec![];
+        let mut at = start;
+        while !visited[at] {
+            visited[at] = true;
+            let edge = next[at].expect("surface loops are closed");
+            polygon.push(edge);
+            at = key(edge);
+        }
+        for n in 1..polygon.len() - 1 {
+            triangles.push([polygon[0], polygon[n], polygon[n + 1]]);
+        }
+    }
+    triangles
+}
@@ -0,0 +1,647 @@
+//! Reader for GROMACS XTC trajectories, including the xdrfile coordinate compression.
+//!
+//! Frames are variable length, so opening a file scans the frame headers once to build
+//! an offset index. After that any frame can be decoded directly. Like `.gro`, XTC is in
+//! nanometers and everything here is converted to angstroms.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::render_pipeline::AtomCpu;
+
+const MAGIC: i32 = 1995;
+const ANGSTROMS_PER_NM: f32 = 10.0;
+
+/// magic, natoms, step, time, 9 box values and the atom count repeated
+const HEADER_LEN: usize = 4 * 13 + 4;
+/// precision, minimum and maximum integer coordinates, initial small index and the
+/// length of the compressed data
+const COMPRESSED_HEADER_LEN: usize = 4 * 9;
+
+/// Frames with this many atoms or fewer are stored as plain floats.
+const MAX_UNCOMPRESSED: usize = 9;
+
+/// Sizes used for the small differences between consecutive atoms, roughly growing by a
+/// factor of 2^(1/3). The index into this table is adjusted as the stream goes on.
+const MAGICINTS: [u32; 73] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 10, 12, 16, 20, 25, 32, 40, 50, 64, 80, 101, 128, 161, 203, 256,
+    322, 406, 512, 645, 812, 1024, 1290, 1625, 2048, 2580, 3250, 4096, 5060, 6501, 8192, 10321,
+    13003, 16384, 20642, 26007, 32768, 41285, 52015, 65536, 82570, 104031, 131072, 165140, 208063,
+    262144, 330280, 416127, 524287, 660561, 832255, 1048576, 1321122, 1664510, 2097152, 2642245,
+    3329021, 4194304, 5284491, 6658042, 8388607, 10568983, 13316085, 16777216,
+];
+const FIRSTIDX: usize = 9;
+
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub step: i32,
+    /// picoseconds
+    pub time: f32,
+    /// Box vectors in angstroms, one per row.
+    pub box_vectors: [[f32; 3]; 3],
+    /// angstroms
+    pub positions: Vec<[f32; 3]>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Invalid { offset: u64, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    BadMagic(i32),
+    /// Every frame of a trajectory must have the same number of atoms, and frames are
+    /// read into that many.
+    AtomCount {
+        expected: usize,
+        found: i32,
+    },
+    /// The compressed coordinates do not decode to the expected number of atoms.
+    Corrupt,
+    NoSuchFrame(usize),
+}
+
+/// Random access to the frames of an XTC file.
+pub struct Reader<R> {
+    inner: R,
+    atoms: usize,
+    /// byte offset of every complete frame
+    offsets: Vec<u64>,
+    /// frame returned by the next call to `next`
+    cursor: usize,
+    buf: Vec<u8>,
+}
+
+struct Header {
+    step: i32,
+    time: f32,
+    box_vectors: [[f32; 3]; 3],
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(BufReader::new(File::open(path).map_err(Error::Io)?))
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Scans the whole input for frame boundaries. A partial frame at the end, as left
+    /// by a run that is still going, is ignored.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let file_len = inner.seek(SeekFrom::End(0)).map_err(Error::Io)?;
+        let mut reader = Self {
+            inner,
+            atoms: 0,
+            offsets: vec![],
+            cursor: 0,
+            buf: vec![],
+        };
+
+        let mut offset = 0;
+        while offset + HEADER_LEN as u64 <= file_len {
+            let mut header = [0; HEADER_LEN];
+            reader.read_at(offset, &mut header)?;
+            let atoms = reader.check_header(offset, &header)?;
+            if reader.offsets.is_empty() {
+                reader.atoms = atoms;
+            }
+
+            let mut len = (HEADER_LEN + 12 * atoms) as u64;
+            if atoms > MAX_UNCOMPRESSED {
+                let at = offset + HEADER_LEN as u64;
+                if at + COMPRESSED_HEADER_LEN as u64 > file_len {
+                    break;
+                }
+                let mut compressed = [0; COMPRESSED_HEADER_LEN];
+                reader.read_at(at, &mut compressed)?;
+                let bytes = be_i32(&compressed, 32).max(0) as u64;
+                len = (HEADER_LEN + COMPRESSED_HEADER_LEN) as u64 + padded(bytes);
+            }
+            if offset + len > file_len {
+                break;
+            }
+            reader.offsets.push(offset);
+            offset += len;
+        }
+
+        reader.seek(0)?;
+        Ok(reader)
+    }
+
+    pub fn atom_count(&self) -> usize {
+        self.atoms
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Make frame `n` the next one returned by the iterator.
+    pub fn seek(&mut self, n: usize) -> Result<(), Error> {
+        if n > self.offsets.len() {
+            return Err(self.no_such_frame(n));
+        }
+        self.cursor = n;
+        Ok(())
+    }
+
+    pub fn frame(&mut self, n: usize) -> Result<Frame, Error> {
+        let mut positions = vec![[0.0; 3]; self.atoms];
+        let header = self.decode(n, |i, p| positions[i] = p)?;
+        Ok(Frame {
+            step: header.step,
+            time: header.time,
+            box_vectors: header.box_vectors,
+            positions,
+        })
+    }
+
+    /// Overwrite the positions of `atoms` with frame `n`, keeping colors and radii.
+    pub fn read_atoms(&mut self, n: usize, atoms: &mut [AtomCpu]) -> Result<(), Error> {
+        if atoms.len() != self.atoms {
+            let offset = *self.offsets.get(n).ok_or_else(|| self.no_such_frame(n))?;
+            return Err(Error::Invalid {
+                offset,
+                kind: ErrorKind::AtomCount {
+                    expected: atoms.len(),
+                    found: self.atoms as i32,
+                },
+            });
+        }
+        self.decode(n, |i, p| atoms[i].pos = p)?;
+        Ok(())
+    }
+
+    fn decode(&mut self, n: usize, mut set: impl FnMut(usize, [f32; 3])) -> Result<Header, Error> {
+        let offset = *self.offsets.get(n).ok_or_else(|| self.no_such_frame(n))?;
+        let mut header = [0; HEADER_LEN];
+        self.read_at(offset, &mut header)?;
+        let float = |i: usize| f32::from_bits(be_i32(&header, i * 4) as u32);
+        let result = Header {
+            step: be_i32(&header, 8),
+            time: float(3),
+            box_vectors: [
+                [float(4), float(5), float(6)],
+                [float(7), float(8), float(9)],
+                [float(10), float(11), float(12)],
+            ]
+            .map(|v| v.map(|x| x * ANGSTROMS_PER_NM)),
+        };
+        let corrupt = Error::Invalid {
+            offset,
+            kind: ErrorKind::Corrupt,
+        };
+
+        if self.atoms <= MAX_UNCOMPRESSED {
+            self.buf.resize(12 * self.atoms, 0);
+            self.inner.read_exact(&mut self.buf).map_err(Error::Io)?;
+            for (i, p) in self.buf.chunks_exact(12).enumerate() {
+                let float = |j: usize| f32::from_bits(be_i32(p, j * 4) as u32) * ANGSTROMS_PER_NM;
+                set(i, [float(0), float(1), float(2)]);
+            }
+            return Ok(result);
+        }
+
+        let mut compressed = [0; COMPRESSED_HEADER_LEN];
+        self.inner.read_exact(&mut compressed).map_err(Error::Io)?;
+        let int = |i: usize| be_i32(&compressed, i * 4);
+        let precision = f32::from_bits(int(0) as u32);
+        let min = [int(1), int(2), int(3)];
+        let max = [int(4), int(5), int(6)];
+        let small_index = int(7);
+        self.buf.resize(padded(int(8).max(0) as u64) as usize, 0);
+        self.inner.read_exact(&mut self.buf).map_err(Error::Io)?;
+
+        let coordinates = Coordinates {
+            atoms: self.atoms,
+            scale: ANGSTROMS_PER_NM / precision,
+            min,
+            max,
+            small_index,
+        };
+        coordinates.decompress(&self.buf, set).ok_or(corrupt)?;
+        Ok(result)
+    }
+
+    /// Returns the number of atoms.
+    fn check_header(&self, offset: u64, header: &[u8]) -> Result<usize, Error> {
+        let invalid = |kind| Error::Invalid { offset, kind };
+        let magic = be_i32(header, 0);
+        if magic != MAGIC {
+            return Err(invalid(ErrorKind::BadMagic(magic)));
+        }
+        let atoms = be_i32(header, 4);
+        let expected = if self.offsets.is_empty() {
+            usize::try_from(atoms).ok()
+        } else {
+            Some(self.atoms)
+        };
+        match expected {
+            Some(expected) if atoms as usize == expected && be_i32(header, 52) == atoms => {
+                Ok(expected)
+            }
+            _ => Err(invalid(ErrorKind::AtomCount {
+                expected: expected.unwrap_or(0),
+                found: atoms,
+            })),
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error> {
+        self.inner
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.inner.read_exact(buf))
+            .map_err(Error::Io)
+    }
+
+    fn no_such_frame(&self, n: usize) -> Error {
+        Error::Invalid {
+            offset: self.offsets.last().copied().unwrap_or(0),
+            kind: ErrorKind::NoSuchFrame(n),
+        }
+    }
+}
+
+impl<R: Read + Seek> Iterator for Reader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor >= self.offsets.len() {
+            return None;
+        }
+        self.cursor += 1;
+        Some(self.frame(self.cursor - 1))
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Frame>, Error> {
+    Reader::open(path)?.collect()
+}
+
+fn be_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// XDR pads opaque data to a multiple of four bytes.
+fn padded(len: u64) -> u64 {
+    len.div_ceil(4) * 4
+}
+
+/// Parameters of one compressed coordinate block.
+///
+/// Coordinates are rounded to integers at the given precision. The first atom of each
+/// run is stored relative to the minimum, using just enough bits for the full range.
+/// The atoms after it are stored as small differences from their predecessor, with a
+/// size that adapts to how close together the preceding atoms were.
+struct Coordinates {
+    atoms: usize,
+    /// angstroms per integer unit
+    scale: f32,
+    min: [i32; 3],
+    max: [i32; 3],
+    small_index: i32,
+}
+
+impl Coordinates {
+    /// Returns None if the data does not decode cleanly.
+    fn decompress(&self, data: &[u8], mut set: impl FnMut(usize, [f32; 3])) -> Option<()> {
+        let mut bits = Bits { data, pos: 0 };
+
+        let sizes: [i64; 3] = std::array::from_fn(|d| self.max[d] as i64 - self.min[d] as i64 + 1);
+        if sizes.iter().any(|&s| s <= 0 || s > u32::MAX as i64) {
+            return None;
+        }
+        let sizes = sizes.map(|s| s as u32);
+        // Ranges too large to pack together are written one coordinate at a time.
+        let large = sizes.iter().any(|&s| s > 0xffffff);
+        let bit_sizes = sizes.map(bits_for_int);
+        let packed_bits = bits_for_ints(sizes);
+
+        let mut small_index = usize::try_from(self.small_index)
+            .ok()
+            .filter(|i| (FIRSTIDX..MAGICINTS.len()).contains(i))?;
+        let mut smaller = MAGICINTS[FIRSTIDX.max(small_index - 1)] as i32 / 2;
+        let mut small_num = MAGICINTS[small_index] as i32 / 2;
+
+        let mut written = 0;
+        let mut emit = |written: &mut usize, c: [i32; 3]| {
+            if *written == self.atoms {
+                return None;
+            }
+            set(*written, c.map(|v| v as f32 * self.scale));
+            *written += 1;
+            Some(())
+        };
+
+        // the run length carries over until the stream changes it
+        let mut run = 0;
+        while written < self.atoms {
+            let mut this = if large {
+                [
+                    bits.read(bit_sizes[0])? as i32,
+                    bits.read(bit_sizes[1])? as i32,
+                    bits.read(bit_sizes[2])? as i32,
+                ]
+            } else {
+                bits.read_ints(packed_bits, sizes)?
+            };
+            for (c, min) in this.iter_mut().zip(self.min) {
+                *c = c.wrapping_add(min);
+            }
+            let mut prev = this;
+
+            let mut is_smaller = 0;
+            if bits.read(1)? == 1 {
+                run = bits.read(5)? as i32;
+                is_smaller = run % 3;
+                run -= is_smaller;
+                is_smaller -= 1;
+            }
+
+            if run > 0 {
+                let small = MAGICINTS[small_index];
+                for k in (0..run).step_by(3) {
+                    let mut next = bits.read_ints(small_index as u32, [small; 3])?;
+                    for d in 0..3 {
+                        next[d] = next[d].wrapping_add(prev[d].wrapping_sub(small_num));
+                    }
+                    if k == 0 {
+                        // the writer swaps the first two atoms of a run, which packs
+                        // water better since the oxygen is then in the middle
+                        std::mem::swap(&mut next, &mut prev);
+                        emit(&mut written, prev)?;
+                    } else {
+                        prev = next;
+                    }
+                    emit(&mut written, next)?;
+                }
+            } else {
+                emit(&mut written, this)?;
+            }
+
+            small_index = small_index.checked_add_signed(is_smaller as isize)?;
+            if !(FIRSTIDX..MAGICINTS.len()).contains(&small_index) {
+                return None;
+            }
+            if is_smaller < 0 {
+                small_num = smaller;
+                smaller = if small_index > FIRSTIDX {
+                    MAGICINTS[small_index - 1] as i32 / 2
+                } else {
+                    0
+                };
+            } else if is_smaller > 0 {
+                smaller = small_num;
+                small_num = MAGICINTS[small_index] as i32 / 2;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Most significant bit first reader over the compressed bytes.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, count: u32) -> Option<u32> {
+        if self.pos + count as usize > self.data.len() * 8 {
+            return None;
+        }
+        let mut value = 0u64;
+        let mut remaining = count;
+        while remaining > 0 {
+            let offset = (self.pos % 8) as u32;
+            let take = (8 - offset).min(remaining);
+            let byte = self.data[self.pos / 8] as u64;
+            value = (value << take) | ((byte >> (8 - offset - take)) & ((1 << take) - 1));
+            self.pos += take as usize;
+            remaining -= take;
+        }
+        Some(value as u32)
+    }
+
+    /// Three integers packed into one big number in mixed radix `sizes`, little endian
+    /// by byte.
+    fn read_ints(&mut self, count: u32, sizes: [u32; 3]) -> Option<[i32; 3]> {
+        let mut bytes = [0u64; 32];
+        let mut len = 0;
+        let mut remaining = count;
+        while remaining > 0 {
+            let take = remaining.min(8);
+            bytes[len] = self.read(take)? as u64;
+            len += 1;
+            remaining -= take;
+        }
+
+        let mut out = [0; 3];
+        for i in (1..3).rev() {
+            let size = sizes[i] as u64;
+            let mut num = 0;
+            for byte in bytes[..len].iter_mut().rev() {
+                num = (num << 8) | *byte;
+                *byte = num / size;
+                num %= size;
+            }
+            out[i] = num as i32;
+        }
+        out[0] = (bytes[0] | bytes[1] << 8 | bytes[2] << 16 | bytes[3] << 24) as i32;
+        Some(out)
+    }
+}
+
+/// Bits needed to store values below `size`.
+fn bits_for_int(size: u32) -> u32 {
+    32 - size.leading_zeros()
+}
+
+/// Bits needed to store the product of `sizes`.
+fn bits_for_ints(sizes: [u32; 3]) -> u32 {
+    let product = sizes.iter().fold(1u128, |p, &s| p * s as u128);
+    // xdrfile counts the bits of product itself, which can be one more than needed
+    128 - product.leading_zeros()
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Invalid { offset, kind } => write!(f, "byte {offset}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::BadMagic(magic) => write!(f, "not an XTC frame, magic number {magic}"),
+            ErrorKind::AtomCount { expected, found } => {
+                write!(f, "expected {expected} atoms, found {found}")
+            }
+            ErrorKind::Corrupt => write!(f, "compressed coordinates are corrupt"),
+            ErrorKind::NoSuchFrame(n) => write!(f, "no frame {n}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Four water molecules and two lone atoms, in nanometers, compressed the way xdrfile
+    /// does at a precision of 1000. The waters exercise runs of small differences and the
+    /// swap of the first two atoms of a run.
+    const POSITIONS: [[f32; 3]; 14] = [
+        [1.0, 1.0, 1.0],
+        [1.096, 1.0, 1.004],
+        [0.976, 1.093, 0.998],
+        [1.5, 1.2, 0.9],
+        [1.596, 1.2, 0.904],
+        [1.476, 1.293, 0.898],
+        [2.1, 0.8, 1.3],
+        [2.196, 0.8, 1.304],
+        [2.076, 0.893, 1.298],
+        [0.6, 1.7, 1.1],
+        [0.696, 1.7, 1.104],
+        [0.576, 1.793, 1.098],
+        [3.25, 0.125, 2.5],
+        [0.05, 2.75, 0.0],
+    ];
+
+    const COMPRESSED: [u8; 62] = [
+        219, 185, 4, 116, 48, 207, 162, 204, 204, 152, 158, 136, 232, 218, 140, 92, 235, 184, 230,
+        233, 87, 210, 182, 186, 162, 134, 199, 101, 46, 81, 109, 136, 22, 164, 57, 159, 32, 8, 31,
+        183, 90, 3, 216, 71, 191, 65, 85, 212, 24, 240, 43, 28, 78, 106, 206, 73, 8, 41, 107, 32,
+        0, 0,
+    ];
+
+    /// The same with the waters moved 0.25 nm along x.
+    const COMPRESSED_MOVED: [u8; 62] = [
+        31, 59, 226, 213, 48, 241, 227, 59, 125, 152, 175, 169, 32, 51, 12, 69, 123, 244, 147, 41,
+        222, 212, 115, 126, 162, 202, 72, 67, 144, 81, 15, 201, 5, 85, 57, 159, 32, 8, 31, 183, 90,
+        20, 248, 190, 215, 137, 85, 212, 24, 240, 43, 28, 78, 106, 206, 73, 8, 41, 107, 32, 0, 0,
+    ];
+
+    fn header(atoms: i32, step: i32, time: f32) -> Vec<u8> {
+        let mut out = vec![];
+        for v in [MAGIC, atoms, step, time.to_bits() as i32] {
+            out.extend(v.to_be_bytes());
+        }
+        for v in [3.0f32, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 0.0, 3.0] {
+            out.extend(v.to_bits().to_be_bytes());
+        }
+        out.extend(atoms.to_be_bytes());
+        out
+    }
+
+    fn compressed_frame(step: i32, data: &[u8]) -> Vec<u8> {
+        let mut out = header(POSITIONS.len() as i32, step, step as f32);
+        out.extend(1000.0f32.to_bits().to_be_bytes());
+        for v in [50, 125, 0, 3250, 2750, 2500, 20, data.len() as i32] {
+            out.extend(v.to_be_bytes());
+        }
+        out.extend(data);
+        out.resize(
+            out.len() + padded(data.len() as u64) as usize - data.len(),
+            0,
+        );
+        out
+    }
+
+    fn assert_close(found: &[[f32; 3]], expected: impl Iterator<Item = [f32; 3]>) {
+        for (i, (f, e)) in found.iter().zip(expected).enumerate() {
+            let e = e.map(|v| v * ANGSTROMS_PER_NM);
+            assert!(
+                (0..3).all(|d| (f[d] - e[d]).abs() < 1e-3),
+                "atom {i}: {f:?} != {e:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_frames() {
+        let mut bytes = compressed_frame(1, &COMPRESSED);
+        bytes.extend(compressed_frame(2, &COMPRESSED_MOVED));
+        let mut reader = Reader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!((reader.atom_count(), reader.len()), (14, 2));
+
+        let moved = reader.frame(1).unwrap();
+        assert_eq!((moved.step, moved.time), (2, 2.0));
+        assert_eq!(moved.box_vectors[1], [0.0, 30.0, 0.0]);
+        assert_close(
+            &moved.positions,
+            POSITIONS
+                .iter()
+                .enumerate()
+                .map(|(i, &[x, y, z])| [if i < 12 { x + 0.25 } else { x }, y, z]),
+        );
+        let first = reader.frame(0).unwrap();
+        assert_close(&first.positions, POSITIONS.into_iter());
+    }
+
+    #[test]
+    fn uncompressed_frames_and_partial_frame() {
+        let mut bytes = vec![];
+        for step in 0..2 {
+            bytes.extend(header(2, step, 0.0));
+            for v in [0.1f32, 0.2, 0.3, -0.4, 0.5, step as f32] {
+                bytes.extend(v.to_bits().to_be_bytes());
+            }
+        }
+        // a frame still being written
+        bytes.extend(&header(2, 2, 0.0)[..20]);
+
+        let frames: Vec<Frame> = Reader::new(Cursor::new(bytes))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_close(
+            &frames[1].positions,
+            [[0.1, 0.2, 0.3], [-0.4, 0.5, 1.0]].into_iter(),
+        );
+    }
+
+    #[test]
+    fn wrong_atom_count_is_an_error() {
+        let mut reader = Reader::new(Cursor::new(compressed_frame(1, &COMPRESSED))).unwrap();
+        let atom = AtomCpu {
+            pos: [0.0; 3],
+            color: [1.0; 3],
+            radius: 1.0,
+        };
+        assert!(matches!(
+            reader.read_atoms(0, &mut [atom; 3]),
+            Err(Error::Invalid {
+                kind: ErrorKind::AtomCount {
+                    expected: 3,
+                    found: 14
+                },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn data_running_out_is_an_error() {
+        let data = &COMPRESSED[..20];
+        let mut reader = Reader::new(Cursor::new(compressed_frame(1, data))).unwrap();
+        assert!(reader.frame(0).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Reader for multi-frame XYZ files, including the extended XYZ conventions for the
+//! comment line (`Lattice="..."`, `Properties=...` and other `key=value` pairs).
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{element, render_pipeline::AtomCpu};
+
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    pub comment: String,
+    /// `key=value` pairs from an extended XYZ comment line, in order of appearance,
+    /// excluding `Lattice` and `Properties`. Keys without a value map to `"T"`.
+    pub info: Vec<(String, String)>,
+    /// Cell vectors in angstroms, one per row.
+    pub lattice: Option<[[f32; 3]; 3]>,
+    pub atoms: Vec<Atom>,
+    /// Per-atom columns other than species and position.
+    pub columns: Vec<Column>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    /// Either an element symbol or an atomic number.
+    pub species: String,
+    pub pos: [f32; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    /// Number of values per atom.
+    pub count: usize,
+    /// `count` values for each atom, atom major.
+    pub data: ColumnData,
+}
+
+#[derive(Clone, Debug)]
+pub enum ColumnData {
+    Str(Vec<String>),
+    Real(Vec<f32>),
+    Int(Vec<i64>),
+    Logical(Vec<bool>),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Parse { line: usize, kind: ErrorKind },
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidAtomCount(String),
+    /// The file ended in the middle of a frame.
+    Truncated,
+    InvalidField {
+        field: String,
+        value: String,
+    },
+    MissingField {
+        field: String,
+    },
+    InvalidProperties(String),
+}
+
+impl Frame {
+    pub fn to_atoms(&self) -> Vec<AtomCpu> {
+        self.atoms.iter().map(Atom::to_atom_cpu).collect()
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+}
+
+impl Atom {
+    pub fn element(&self) -> &'static element::Element {
+        match self.species.parse() {
+            Ok(number) => element::by_number(number).unwrap_or(&element::UNKNOWN),
+            Err(_) => element::lookup_or_unknown(&self.species),
+        }
+    }
+
+    pub fn to_atom_cpu(&self) -> AtomCpu {
+        let element = self.element();
+        AtomCpu {
+            pos: self.pos,
+            color: element.color,
+            radius: element.radius,
+        }
+    }
+}
+
+/// Streams frames out of a reader one at a time, so long trajectories need not be held
+/// in memory at once.
+pub struct Reader<R> {
+    inner: R,
+    line: usize,
+    buf: String,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(BufReader::new(
+            File::open(path).map_err(Error::Io)?,
+        )))
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Returns false at end of input.
+    fn next_line(&mut self) -> Result<bool, Error> {
+        self.buf.clear();
+        let read = self.inner.read_line(&mut self.buf).map_err(Error::Io)?;
+        self.line += 1;
+        let trimmed = self.buf.trim_end_matches(['\n', '\r']).len();
+        self.buf.truncate(trimmed);
+        Ok(read != 0)
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Parse {
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        // skip blank lines between frames
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            if !self.buf.trim().is_empty() {
+                break;
+            }
+        }
+        let count: usize = self
+            .buf
+            .trim()
+            .parse()
+            .map_err(|_| self.error(ErrorKind::InvalidAtomCount(self.buf.clone())))?;
+
+        if !self.next_line()? {
+            return Err(self.error(ErrorKind::Truncated));
+        }
+        let comment = self.buf.clone();
+        let mut frame = Frame {
+            comment,
+            ..Default::default()
+        };
+        let mut properties = None;
+        if let Some(extended) = parse_extended(&frame.comment) {
+            frame.info = extended.info;
+            frame.lattice = extended.lattice;
+            properties = extended.properties;
+        }
+        let properties = properties.unwrap_or_else(|| {
+            vec![
+                Property::new("species", Kind::Str, 1),
+                Property::new("pos", Kind::Real, 3),
+            ]
+        });
+        for (required, count) in [("species", 1), ("pos", 3)] {
+            if !properties
+                .iter()
+                .any(|p| p.name == required && p.count == count)
+            {
+                let kind = ErrorKind::InvalidProperties(format!(
+                    "expected a {required} column with {count} values"
+                ));
+                return Err(self.error(kind));
+            }
+        }
+
+        frame.atoms.reserve(count);
+        frame.columns = properties
+            .iter()
+            .filter(|p| p.name != "species" && p.name != "pos")
+            .map(|p| Column {
+                name: p.name.clone(),
+                count: p.count,
+                data: p.kind.empty_data(),
+            })
+            .collect();
+
+        for _ in 0..count {
+            if !self.next_line()? {
+                return Err(self.error(ErrorKind::Truncated));
+            }
+            let atom = parse_atom(&self.buf, &properties, &mut frame.columns)
+                .map_err(|k| self.error(k))?;
+            frame.atoms.push(atom);
+        }
+
+        Ok(Some(frame))
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+pub fn parse(src: &str) -> Result<Vec<Frame>, Error> {
+    Reader::new(src.as_bytes()).collect()
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Frame>, Error> {
+    Reader::open(path)?.collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Str,
+    Real,
+    Int,
+    Logical,
+}
+
+impl Kind {
+    fn empty_data(self) -> ColumnData {
+        match self {
+            Kind::Str => ColumnData::Str(vec![]),
+            Kind::Real => ColumnData::Real(vec![]),
+            Kind::Int => ColumnData::Int(vec![]),
+            Kind::Logical => ColumnData::Logical(vec![]),
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+    count: usize,
+}
+
+impl Property {
+    fn new(name: &str, kind: Kind, count: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            count,
+        }
+    }
+}
+
+/// `Properties=species:S:1:pos:R:3:forces:R:3`
+fn parse_properties(value: &str) -> Result<Vec<Property>, ErrorKind> {
+    let invalid = || ErrorKind::InvalidProperties(value.to_string());
+    let parts: Vec<&str> = value.split(':').collect();
+    if !parts.len().is_multiple_of(3) {
+        return Err(invalid());
+    }
+    parts
+        .chunks(3)
+        .map(|p| {
+            let kind = match p[1] {
+                "S" | "s" => Kind::Str,
+                "R" | "r" => Kind::Real,
+                "I" | "i" => Kind::Int,
+                "L" | "l" => Kind::Logical,
+                _ => return Err(invalid()),
+            };
+            let count = p[2].parse().map_err(|_| invalid())?;
+            Ok(Property::new(p[0], kind, count))
+        })
+        .collect()
+}
+
+fn parse_lattice(value: &str) -> Option<[[f32; 3]; 3]> {
+    let numbers = value
+        .split_whitespace()
+        .map(|s| s.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    if numbers.len() != 9 {
+        return None;
+    }
+    Some([
+        [numbers[0], numbers[1], numbers[2]],
+        [numbers[3], numbers[4], numbers[5]],
+        [numbers[6], numbers[7], numbers[8]],
+    ])
+}
+
+/// What an extended XYZ comment line says about its frame.
+struct Extended {
+    info: Vec<(String, String)>,
+    lattice: Option<[[f32; 3]; 3]>,
+    properties: Option<Vec<Property>>,
+}
+
+/// None for a plain XYZ comment line. Plain comments are free text, so a line is only
+/// taken as extended when it has `key=value` pairs, and one whose `Lattice` or
+/// `Properties` do not parse is taken as plain text after all.
+fn parse_extended(comment: &str) -> Option<Extended> {
+    let mut extended = Extended {
+        info: vec![],
+        lattice: None,
+        properties: None,
+    };
+    for (key, value) in parse_comment(comment)? {
+        if key.eq_ignore_ascii_case("Lattice") {
+            extended.lattice = Some(parse_lattice(&value)?);
+        } else if key.eq_ignore_ascii_case("Properties") {
+            extended.properties = Some(parse_properties(&value).ok()?);
+        } else {
+            extended.info.push((key, value));
+        }
+    }
+    Some(extended)
+}
+
+/// Split an extended XYZ comment line into key value pairs. Values may be quoted with
+/// `"` or wrapped in `{}`. Returns None if there is no `=` pair at all, leaving only bare
+/// words.
+fn parse_comment(comment: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    let mut any_value = false;
+    let mut rest = comment.trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_string();
+        rest = &rest[key_end..];
+
+        let value = match rest.strip_prefix('=') {
+            None => "T".to_string(),
+            Some(after) => {
+                any_value = true;
+                let close = match after.chars().next() {
+                    Some('"') => Some('"'),
+                    Some('{') => Some('}'),
+                    _ => None,
+                };
+                match close {
+                    Some(close) => {
+                        // an unterminated quote takes the rest of the line
+                        let end = after[1..].find(close).unwrap_or(after.len() - 1);
+                        rest = after.get(end + 2..).unwrap_or_default();
+                        after[1..end + 1].to_string()
+                    }
+                    None => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        rest = &after[end..];
+                        after[..end].to_string()
+                    }
+                }
+            }
+        };
+        pairs.push((key, value));
+        rest = rest.trim_start();
+    }
+    any_value.then_some(pairs)
+}
+
+fn parse_atom(
+    line: &str,
+    properties: &[Property],
+    columns: &mut [Column],
+) -> Result<Atom, ErrorKind> {
+    let mut fields = line.split_whitespace();
+    let mut species = None;
+    let mut pos = None;
+    let mut extra = columns.iter_mut();
+
+    for property in properties {
+        let mut take = || {
+            fields.next().ok_or_else(|| ErrorKind::MissingField {
+                field: property.name.clone(),
+            })
+        };
+        let invalid = |value: &str| ErrorKind::InvalidField {
+            field: property.name.clone(),
+            value: value.to_string(),
+        };
+        match property.name.as_str() {
+            "species" => species = Some(take()?.to_string()),
+            "pos" => {
+                let mut p = [0.0; 3];
+                for v in p.iter_mut() {
+                    let s = take()?;
+                    *v = s.parse().map_err(|_| invalid(s))?;
+                }
+                pos = Some(p);
+            }
+            _ => {
+                let column = extra.next().expect("one column per extra property");
+                for _ in 0..property.count {
+                    let s = take()?;
+                    match &mut column.data {
+                        ColumnData::Str(v) => v.push(s.to_string()),
+                        ColumnData::Real(v) => v.push(s.parse().map_err(|_| invalid(s))?),
+                        ColumnData::Int(v) => v.push(s.parse().map_err(|_| invalid(s))?),
+                        ColumnData::Logical(v) => v.push(match s {
+                            "T" | "t" | "True" | "true" | "1" => true,
+                            "F" | "f" | "False" | "false" | "0" => false,
+                            _ => return Err(invalid(s)),
+                        }),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Atom {
+        species: species.expect("species is a required property"),
+        pos: pos.expect("pos is a required property"),
+    })
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse { line, kind } => write!(f, "line {line}: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidAtomCount(s) => write!(f, "invalid atom count: {s:?}"),
+            ErrorKind::Truncated => write!(f, "file ends in the middle of a frame"),
+            ErrorKind::InvalidField { field, value } => {
+                write!(f, "invalid value for {field}: {value:?}")
+            }
+            ErrorKind::MissingField { field } => write!(f, "missing field {field}"),
+            ErrorKind::InvalidProperties(s) => write!(f, "invalid Properties: {s:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}